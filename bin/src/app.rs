@@ -2,31 +2,55 @@ use piston::input::*;
 use opengl_graphics::{GlGraphics, OpenGL, Texture, TextureSettings};
 use graphics::{DrawState, Transformed};
 use libraytracer::prelude::*;
-use libraytracer::{RayTracer, Camera, SamplingConfig};
+use libraytracer::{RayTracer, Camera, SamplingConfig, ToneMapper, Denoiser, Image};
+use libraytracer::aov::Aov;
+use libraytracer::progress::{Progress, CancellationToken};
+use libraytracer::tone_mapping::{Operator, Transfer};
 use libraytracer::entity::Sphere;
 use libraytracer::brdf;
 use std::f32;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::Duration;
 use graphics;
 
-type BrdfType = brdf::UnlimitedChromatic;
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
 
-pub struct RayTracerApp {
-    gl: GlGraphics,
-    raytracer: RayTracer<BrdfType>,
-    mouse_coord: (f64, f64),
-    left_mouse_down: bool,
-    right_mouse_down: bool,
-    arrow_up_pressed: bool,
-    arrow_down_pressed: bool,
-    arrow_left_pressed: bool,
-    arrow_right_pressed: bool,
-    window_scale: f64,
+/// What the window asks the rendering thread to do.
+enum Command {
+    /// Moves the sphere that's controlled with the arrow keys, which throws
+    /// the image away.
+    Move(Vec3),
+    SetPaused(bool),
+    SetDenoiser(Option<Denoiser>),
 }
 
-impl RayTracerApp {
-    pub fn new(opengl: OpenGL, dimensions: (usize, usize)) -> Self {
+/// The state of the image after a pass, sent back to the window.
+struct Frame {
+    image: Image,
+    /// The image after the last finished pass, denoised.
+    denoised: Option<Image>,
+    passes: usize,
+}
+
+/// Owns the ray tracer and renders pass after pass on a thread of its own,
+/// so that the window stays responsive during long passes.
+struct Renderer {
+    raytracer: RayTracer,
+    denoiser: Option<Denoiser>,
+    denoised: Option<Image>,
+    passes: usize,
+    paused: bool,
+}
+
+impl Renderer {
+    fn new(dimensions: (usize, usize), denoiser: Option<Denoiser>) -> Self {
         let camera = Camera::new(dimensions, Vec3::new(0.0, 0.0, 0.0), f32::consts::FRAC_PI_2);
-        let config = SamplingConfig::new(5, 1, 1.0);
+        let config = SamplingConfig::path_tracing(3, 64);
 
         let mut raytracer = RayTracer::new(camera, config);
 
@@ -46,15 +70,185 @@ impl RayTracerApp {
         let sphere = Sphere::new(Vec3::new(-3.0, 0.0, 7.0), 2.0, brdf);
         raytracer.add_entity(sphere);
 
-        let brdf = brdf::UnlimitedChromatic::new(Rgb::new(0.7, 0.23, 0.12),
-                                                 Rgb::new(0.0, 0.0, 0.0),
-                                                 0.8);
+        let brdf = brdf::Lambert::new(Rgb::new(0.7, 0.23, 0.12));
         let ground = Sphere::new(Vec3::new(0.0, -1002.0, 8.0), 1000.0, brdf);
         raytracer.add_entity(ground);
 
+        // The denoiser is guided by these
+        raytracer.enable_aov(Aov::Albedo);
+        raytracer.enable_aov(Aov::Normal);
+
+        Renderer {
+            raytracer: raytracer,
+            denoiser: denoiser,
+            denoised: None,
+            passes: 0,
+            paused: false,
+        }
+    }
+
+    fn handle(&mut self, command: Command) {
+        match command {
+            Command::Move(offset) => {
+                {
+                    let sphere = self.raytracer.entity_mut(0);
+                    let new_position = sphere.position() + offset;
+                    sphere.set_position(new_position);
+                }
+                self.raytracer.clear_image();
+                self.passes = 0;
+                self.denoised = None;
+            }
+            Command::SetPaused(paused) => self.paused = paused,
+            Command::SetDenoiser(denoiser) => {
+                self.denoiser = denoiser;
+                self.denoised = None;
+                if self.passes > 0 {
+                    self.denoise();
+                }
+            }
+        }
+    }
+
+    /// Denoising takes a while, so it only happens once a pass is done.
+    fn denoise(&mut self) {
+        let raytracer = &self.raytracer;
+        self.denoised = self.denoiser.and_then(|denoiser| raytracer.denoised_image(&denoiser));
+    }
+
+    fn frame(&self) -> Frame {
+        Frame {
+            image: self.raytracer.image.clone(),
+            denoised: self.denoised.clone(),
+            passes: self.passes,
+        }
+    }
+
+    /// Renders until the window goes away. The window cancels the token
+    /// after every command, so a command never waits for a pass to finish.
+    fn run(mut self,
+           commands: Receiver<Command>,
+           frames: Sender<Frame>,
+           cancellation_token: Arc<Mutex<CancellationToken>>,
+           progress: Arc<Mutex<Option<Progress>>>) {
+        {
+            let progress = progress.clone();
+            self.raytracer.set_progress_callback(move |current| {
+                *progress.lock().unwrap() = Some(current.clone());
+            });
+        }
+
+        // Whether commands changed the image since the last frame was sent
+        let mut changed = false;
+
+        loop {
+            // A cancelled token stays cancelled, so every pass needs a new
+            // one. It's swapped in before looking for commands, so that a
+            // command that arrives after that cancels the pass.
+            let token = CancellationToken::new();
+            *cancellation_token.lock().unwrap() = token.clone();
+            self.raytracer.set_cancellation_token(token);
+
+            loop {
+                match commands.try_recv() {
+                    Ok(command) => {
+                        self.handle(command);
+                        changed = true;
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            }
+
+            if self.paused {
+                // Show the cleared image of a moved sphere right away
+                if changed {
+                    if frames.send(self.frame()).is_err() {
+                        return;
+                    }
+                    changed = false;
+                }
+                match commands.recv() {
+                    Ok(command) => {
+                        self.handle(command);
+                        changed = true;
+                    }
+                    Err(_) => return,
+                }
+                continue;
+            }
+
+            if self.raytracer.render() {
+                self.passes += 1;
+                self.denoise();
+            }
+            *progress.lock().unwrap() = None;
+
+            // Cancelled passes still get shown, as the finished tiles keep
+            // their samples
+            if frames.send(self.frame()).is_err() {
+                return;
+            }
+            changed = false;
+        }
+    }
+}
+
+pub struct RayTracerApp {
+    gl: GlGraphics,
+    tone_mapper: ToneMapper,
+    /// Shows a denoised preview instead of the raw image.
+    denoiser: Option<Denoiser>,
+    /// The latest pass the rendering thread sent.
+    frame: Frame,
+    commands: Sender<Command>,
+    frames: Receiver<Frame>,
+    /// The token of the pass that's being rendered.
+    cancellation_token: Arc<Mutex<CancellationToken>>,
+    /// How far the pass that's being rendered has come.
+    progress: Arc<Mutex<Option<Progress>>>,
+    paused: bool,
+    mouse_coord: (f64, f64),
+    left_mouse_down: bool,
+    right_mouse_down: bool,
+    arrow_up_pressed: bool,
+    arrow_down_pressed: bool,
+    arrow_left_pressed: bool,
+    arrow_right_pressed: bool,
+    window_scale: f64,
+}
+
+impl RayTracerApp {
+    pub fn new(opengl: OpenGL, dimensions: (usize, usize)) -> Self {
+        let (commands, receiver) = mpsc::channel();
+        let (sender, frames) = mpsc::channel();
+        let cancellation_token = Arc::new(Mutex::new(CancellationToken::new()));
+        let progress = Arc::new(Mutex::new(None));
+        let denoiser = Some(Denoiser::new());
+
+        {
+            let cancellation_token = cancellation_token.clone();
+            let progress = progress.clone();
+            thread::spawn(move || {
+                Renderer::new(dimensions, denoiser)
+                    .run(receiver, sender, cancellation_token, progress);
+            });
+        }
+
         RayTracerApp {
             gl: GlGraphics::new(opengl),
-            raytracer: raytracer,
+            tone_mapper: ToneMapper::new(Operator::Clamp, 0.0, Transfer::Gamma(1.0)),
+            denoiser: denoiser,
+            frame: Frame {
+                image: Image::new(dimensions),
+                denoised: None,
+                passes: 0,
+            },
+            commands: commands,
+            frames: frames,
+            cancellation_token: cancellation_token,
+            progress: progress,
+            paused: false,
             mouse_coord: (0.0, 0.0),
             left_mouse_down: false,
             right_mouse_down: false,
@@ -67,12 +261,14 @@ impl RayTracerApp {
     }
 
     pub fn render(&mut self, args: &RenderArgs) {
+        let image = match self.denoised() {
+            Some(denoised) => denoised.tone_map(&self.tone_mapper),
+            None => self.frame.image.tone_map(&self.tone_mapper),
+        };
         let scale = &mut self.window_scale;
-        let raytracer = &self.raytracer;
 
         self.gl.draw(args.viewport(), |c, gl| {
             graphics::clear([0.0, 0.0, 0.0, 1.0], gl);
-            let image = raytracer.image.to_rgba_image(1.0);
             let texture = Texture::from_image(&image, &TextureSettings::new());
             let image = graphics::Image::new();
             let w = args.width as f64 / 800.0;
@@ -89,37 +285,95 @@ impl RayTracerApp {
         });
     }
 
+    /// The denoised preview, unless the denoiser got turned off since the
+    /// frame was rendered.
+    fn denoised(&self) -> Option<&Image> {
+        match self.denoiser {
+            Some(_) => self.frame.denoised.as_ref(),
+            None => None,
+        }
+    }
+
+    /// Hands a command to the rendering thread and cancels the pass that's
+    /// being rendered, so that the command takes effect right away.
+    fn send(&self, command: Command) {
+        let _ = self.commands.send(command);
+        self.cancellation_token.lock().unwrap().cancel();
+    }
+
     pub fn update(&mut self, _: &UpdateArgs) {
-        let invalidated = {
-            let sphere = self.raytracer.entity_mut(0);
-            if self.arrow_up_pressed {
-                let new_position = sphere.position() + Vec3::new(0.0, 0.0, 0.1);
-                sphere.set_position(new_position);
-                true
-            } else if self.arrow_down_pressed {
-                let new_position = sphere.position() + Vec3::new(0.0, 0.0, -0.1);
-                sphere.set_position(new_position);
-                true
-            } else if self.arrow_left_pressed {
-                let new_position = sphere.position() + Vec3::new(-0.1, 0.0, 0.0);
-                sphere.set_position(new_position);
-                true
-            } else if self.arrow_right_pressed {
-                let new_position = sphere.position() + Vec3::new(0.1, 0.0, 0.0);
-                sphere.set_position(new_position);
-                true
-            } else {
-                false
-            }
+        let offset = if self.arrow_up_pressed {
+            Some(Vec3::new(0.0, 0.0, 0.1))
+        } else if self.arrow_down_pressed {
+            Some(Vec3::new(0.0, 0.0, -0.1))
+        } else if self.arrow_left_pressed {
+            Some(Vec3::new(-0.1, 0.0, 0.0))
+        } else if self.arrow_right_pressed {
+            Some(Vec3::new(0.1, 0.0, 0.0))
+        } else {
+            None
         };
-        if invalidated {
-            self.raytracer.clear_image();
+        if let Some(offset) = offset {
+            self.send(Command::Move(offset));
+        }
+
+        while let Ok(frame) = self.frames.try_recv() {
+            self.frame = frame;
         }
-        self.raytracer.render();
+    }
+
+    /// Shows how many passes the image has and how far the current pass
+    /// has come, to go into the window title.
+    pub fn title(&self) -> String {
+        let mut title = format!("Raytracer - {} passes", self.frame.passes);
+        if let Some(ref progress) = *self.progress.lock().unwrap() {
+            title.push_str(&format!(", {}/{} tiles", progress.tiles_done, progress.tile_count));
+            if let Some(remaining) = progress.remaining() {
+                title.push_str(&format!(", {} left", format_duration(remaining)));
+            }
+        }
+        if self.denoised().is_some() {
+            title.push_str(" (denoised)");
+        }
+        if self.paused {
+            title.push_str(" (paused)");
+        }
+        title
+    }
+
+    /// Switches to the next tone mapping operator.
+    fn cycle_operator(&mut self) {
+        let operators = Operator::all();
+        let index = operators.iter()
+                             .position(|&operator| operator == self.tone_mapper.operator)
+                             .unwrap_or(0);
+        self.tone_mapper.operator = operators[(index + 1) % operators.len()];
     }
 
     fn handle_key_press(&mut self, key: Key, press: bool) {
         match key {
+            Key::T if press => self.cycle_operator(),
+            Key::P if press => {
+                self.paused = !self.paused;
+                self.send(Command::SetPaused(self.paused));
+            }
+            Key::D if press => {
+                self.denoiser = match self.denoiser {
+                    Some(_) => None,
+                    None => Some(Denoiser::new()),
+                };
+                // This doesn't need to cancel the pass, as denoising only
+                // happens after a pass anyway
+                let _ = self.commands.send(Command::SetDenoiser(self.denoiser));
+            }
+            Key::S if press => {
+                self.tone_mapper.transfer = match self.tone_mapper.transfer {
+                    Transfer::Srgb => Transfer::Gamma(1.0),
+                    Transfer::Gamma(_) => Transfer::Srgb,
+                };
+            }
+            Key::Equals if press => self.tone_mapper.exposure += 0.5,
+            Key::Minus if press => self.tone_mapper.exposure -= 0.5,
             Key::Up => {
                 self.arrow_up_pressed = press;
             }
@@ -7,7 +7,7 @@ extern crate image;
 extern crate palette;
 extern crate nalgebra;
 
-use piston::window::WindowSettings;
+use piston::window::{WindowSettings, AdvancedWindow};
 use piston::event_loop::*;
 use piston::input::*;
 use glutin_window::GlutinWindow as Window;
@@ -37,7 +37,10 @@ fn main() {
     while let Some(e) = event_loop.next(&mut window) {
         match e {
             Event::Render(r) => app.render(&r),
-            Event::Update(u) => app.update(&u),
+            Event::Update(u) => {
+                app.update(&u);
+                window.set_title(app.title());
+            }
             Event::Input(i) => app.handle_input(&i),
             _ => {}
         }
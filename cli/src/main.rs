@@ -2,57 +2,141 @@ extern crate libraytracer;
 extern crate image;
 extern crate palette;
 extern crate nalgebra;
+extern crate toml;
+extern crate ctrlc;
 
-use libraytracer::prelude::*;
-use libraytracer::{RayTracer, Camera};
-use libraytracer::entity::{Sphere, Triangle, Mesh};
-use libraytracer::raytracer::SamplingConfig;
-use libraytracer::brdf;
-use std::f32;
+mod scene;
+
+use libraytracer::Denoiser;
+use libraytracer::progress::{Progress, CancellationToken};
+use std::env;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+fn report(progress: &Progress) {
+    let remaining = match progress.remaining() {
+        Some(remaining) => format_duration(remaining),
+        None => "-:--:--".to_owned(),
+    };
+    let _ = write!(io::stderr(),
+                   "\rPass {}/{}, {:5.1}% done, {} elapsed, {} remaining ",
+                   progress.pass + 1,
+                   progress.pass_count,
+                   100.0 * progress.fraction(),
+                   format_duration(progress.elapsed),
+                   remaining);
+}
 
 fn main() {
-    let dimensions = (500, 500);
-    let camera = Camera::new(dimensions, Vec3::new(0.0, 0.0, 0.0), f32::consts::FRAC_PI_2);
-    let config = SamplingConfig::new(5, 1, 1.0);
-
-    let mut raytracer = RayTracer::new(camera, config);
-
-    //let brdf = brdf::Broken::new(Rgb::new(0.8, 0.4, 0.0), 0.1, 0.2, Rgb::new(0.0, 0.0, 0.0));
-    // let brdf = brdf::Lambert::new(Rgb::new(0.1, 0.1, 0.0));
-    /*let brdf = brdf::UnlimitedChromatic::new(Rgb::new(0.8, 0.4, 0.0), Rgb::new(0.1, 0.1, 0.1), 0.2);
-    let sphere = Sphere::new(Vec3::new(3.0, 0.0, 9.0), 2.0, brdf);
-    raytracer.add_entity(sphere);
-
-    //let brdf = brdf::Broken::new(Rgb::new(0.0, 0.02, 0.8), 0.9, 0.4, Rgb::new(0.0, 0.0, 0.0));
-    // let brdf = brdf::Lambert::new(Rgb::new(0.0, 0.2, 0.9));
-     let brdf = brdf::UnlimitedChromatic::new(Rgb::new(0.0, 0.02, 0.8),
-     Rgb::new(0.99, 0.9, 0.95),
-     0.4);
-
-    let sphere = Sphere::new(Vec3::new(-3.0, 0.0, 7.0), 2.0, brdf);
-    raytracer.add_entity(sphere);*/
-
-    //let brdf = brdf::Broken::new(Rgb::new(0.7, 0.23, 0.12), 0.0, 0.8, Rgb::new(0.0, 0.0, 0.0));
-    let brdf = brdf::UnlimitedChromatic::new(Rgb::new(0.7, 0.23, 0.12),
-    Rgb::new(0.0, 0.0, 0.0),
-    0.8);
-    let ground = Sphere::new(Vec3::new(0.0, -1002.0, 8.0), 1000.0, brdf);
-    raytracer.add_entity(ground);
-
-    let brdf = brdf::UnlimitedChromatic::new(Rgb::new(0.0, 0.8, 0.0), Rgb::new(0.1, 0.1, 0.1), 0.2);
-    let triangle = Triangle::new(Vec3::new(0.0, 3.0, 9.0),
-                                 Vec3::new(3.0, 3.0, 9.0),
-                                 Vec3::new(3.0, 6.0, 9.0));
-    let mesh = Mesh::new(vec![triangle], brdf);
-
-    raytracer.add_entity(mesh);
-
-    for _ in 0..500 {
-        raytracer.render();
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            let _ = writeln!(io::stderr(), "Usage: raytracer <scene.toml>");
+            process::exit(1);
+        }
+    };
+
+    let scene = match scene::load(&path) {
+        Ok(scene) => scene,
+        Err(error) => {
+            let _ = writeln!(io::stderr(), "Couldn't load the scene {}: {}", path, error);
+            process::exit(1);
+        }
+    };
+
+    let mut raytracer = scene.raytracer;
+    let settings = scene.settings;
+
+    // The first Ctrl-C stops the rendering, but the image still gets saved
+    let cancellation_token = CancellationToken::new();
+    raytracer.set_cancellation_token(cancellation_token.clone());
+    {
+        let cancellation_token = cancellation_token.clone();
+        if let Err(error) = ctrlc::set_handler(move || cancellation_token.cancel()) {
+            let _ = writeln!(io::stderr(), "Couldn't handle Ctrl-C: {}", error);
+        }
+    }
+
+    // Reporting every tile would flood the terminal
+    let last_report = Mutex::new(None::<Instant>);
+    raytracer.set_progress_callback(move |progress| {
+        let mut last_report = last_report.lock().unwrap();
+        let pass_done = progress.tiles_done == progress.tile_count;
+        let due = last_report.map_or(true, |last| last.elapsed() > Duration::from_millis(200));
+        if pass_done || due {
+            report(progress);
+            *last_report = Some(Instant::now());
+        }
+    });
+
+    let passes = raytracer.render_passes(settings.passes);
+    let _ = writeln!(io::stderr(), "");
+    if cancellation_token.is_cancelled() {
+        let _ = writeln!(io::stderr(), "Cancelled after {} passes", passes);
+    } else if passes < settings.passes {
+        let _ = writeln!(io::stderr(), "Converged after {} passes", passes);
     }
 
-    let image = raytracer.image.to_rgba_image(2.2);
+    if settings.denoise {
+        if let Some(denoised) = raytracer.denoised_image(&Denoiser::new()) {
+            raytracer.image = denoised;
+        }
+    }
 
-    std::fs::remove_file("rendered.png").expect("Couldn't remove the image");
-    image.save("rendered.png").expect("Couldn't save the image");
+    // High dynamic range formats get the radiance without any tone mapping
+    let extension = settings.output
+                            .extension()
+                            .and_then(|extension| extension.to_str())
+                            .map(|extension| extension.to_lowercase());
+    let is_exr = extension.as_ref().map_or(false, |extension| extension == "exr");
+    let layered_aovs = is_exr && !settings.separate_aovs && !settings.aovs.is_empty();
+    let result = match extension.as_ref().map(|extension| &extension[..]) {
+        Some("exr") if layered_aovs => raytracer.save_exr_layers(&settings.output),
+        Some("exr") => raytracer.image.save_exr(&settings.output),
+        Some("hdr") => raytracer.image.save_hdr(&settings.output),
+        _ => raytracer.image.tone_map(&settings.tone_mapper).save(&settings.output),
+    };
+
+    if let Err(error) = result {
+        let _ = writeln!(io::stderr(),
+                         "Couldn't save the image {}: {}",
+                         settings.output.display(),
+                         error);
+        process::exit(1);
+    }
+
+    if !layered_aovs {
+        // Saved as <output>.<aov>.exr
+        let stem = settings.output.file_stem().and_then(|stem| stem.to_str()).unwrap_or("");
+        let directory = settings.output.parent().unwrap_or(Path::new(""));
+        for &aov in &settings.aovs {
+            let path = directory.join(format!("{}.{}.exr", stem, aov.name()));
+            if let Err(error) = raytracer.save_aov_exr(aov, &path) {
+                let _ = writeln!(io::stderr(),
+                                 "Couldn't save the {} AOV {}: {}",
+                                 aov.name(),
+                                 path.display(),
+                                 error);
+                process::exit(1);
+            }
+        }
+    }
+
+    if let Some(ref path) = settings.sample_counts {
+        if let Err(error) = raytracer.image.sample_count_image().save(path) {
+            let _ = writeln!(io::stderr(),
+                             "Couldn't save the sample counts {}: {}",
+                             path.display(),
+                             error);
+            process::exit(1);
+        }
+    }
 }
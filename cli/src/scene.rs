@@ -0,0 +1,775 @@
+use libraytracer::prelude::*;
+use libraytracer::{RayTracer, Camera, SamplingConfig, AdaptiveSampling, Sampler, ToneMapper,
+                   Transform};
+use libraytracer::entity::{Sphere, Triangle, Mesh, Aperture, Projection};
+use libraytracer::brdf::{Lambert, BlinnPhong, UnlimitedChromatic, Broken};
+use libraytracer::obj;
+use libraytracer::light::{PointLight, DirectionalLight, SpotLight, AreaLight};
+use libraytracer::environment::{Environment, Constant, Gradient, Sky, Sun, EnvironmentMap};
+use libraytracer::sampler::{Independent, Stratified, Halton, Sobol, BlueNoise};
+use libraytracer::tile::TileOrder;
+use libraytracer::aov::Aov;
+use libraytracer::tone_mapping::{Operator, Transfer};
+use nalgebra as na;
+use toml::{Parser, Value, Table};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::f32;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Syntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    Invalid {
+        key: String,
+        message: String,
+    },
+    Obj(obj::Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<obj::Error> for Error {
+    fn from(error: obj::Error) -> Self {
+        Error::Obj(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref error) => write!(f, "{}", error),
+            Error::Syntax { line, column, ref message } => {
+                write!(f, "{}:{}: {}", line, column, message)
+            }
+            Error::Invalid { ref key, ref message } => write!(f, "{}: {}", key, message),
+            Error::Obj(ref error) => write!(f, "{}", error),
+        }
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// A BRDF that's only known at runtime. It's reference counted, so that all
+/// the meshes loaded from a single mesh file can share a BRDF override.
+pub type Material = Arc<Brdf + Send + Sync>;
+
+pub struct RenderSettings {
+    pub passes: usize,
+    /// Rendering a scene with the same seed always results in the same image.
+    pub seed: u64,
+    pub tile_size: usize,
+    pub tile_order: TileOrder,
+    pub tone_mapper: ToneMapper,
+    /// Images ending in .exr or .hdr keep the full dynamic range.
+    pub output: PathBuf,
+    /// Where to save the number of samples of every pixel, for debugging
+    /// adaptive sampling.
+    pub sample_counts: Option<PathBuf>,
+    /// The AOVs to render alongside the image. They become layers of the
+    /// output if it's an OpenEXR image, unless `separate_aovs` is set.
+    pub aovs: Vec<Aov>,
+    /// Saves every AOV to its own OpenEXR image next to the output.
+    pub separate_aovs: bool,
+    /// Denoises the final image, which needs the albedo and normal AOVs.
+    pub denoise: bool,
+}
+
+pub struct Scene {
+    pub raytracer: RayTracer,
+    pub settings: RenderSettings,
+}
+
+trait FromValue: Sized {
+    fn from_value(value: &Value) -> Option<Self>;
+    fn expected() -> &'static str;
+}
+
+impl FromValue for f32 {
+    fn from_value(value: &Value) -> Option<f32> {
+        match *value {
+            Value::Float(value) => Some(value as f32),
+            Value::Integer(value) => Some(value as f32),
+            _ => None,
+        }
+    }
+
+    fn expected() -> &'static str {
+        "a number"
+    }
+}
+
+impl FromValue for usize {
+    fn from_value(value: &Value) -> Option<usize> {
+        match *value {
+            Value::Integer(value) if value >= 0 => Some(value as usize),
+            _ => None,
+        }
+    }
+
+    fn expected() -> &'static str {
+        "a non-negative integer"
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Option<bool> {
+        value.as_bool()
+    }
+
+    fn expected() -> &'static str {
+        "a boolean"
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Option<String> {
+        value.as_str().map(String::from)
+    }
+
+    fn expected() -> &'static str {
+        "a string"
+    }
+}
+
+impl FromValue for Aov {
+    fn from_value(value: &Value) -> Option<Aov> {
+        value.as_str().and_then(Aov::from_name)
+    }
+
+    fn expected() -> &'static str {
+        "the name of an AOV"
+    }
+}
+
+fn triple(value: &Value) -> Option<(f32, f32, f32)> {
+    match value.as_slice() {
+        Some(values) if values.len() == 3 => {
+            match (f32::from_value(&values[0]),
+                   f32::from_value(&values[1]),
+                   f32::from_value(&values[2])) {
+                (Some(x), Some(y), Some(z)) => Some((x, y, z)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+impl FromValue for Vec3 {
+    fn from_value(value: &Value) -> Option<Vec3> {
+        triple(value).map(|(x, y, z)| Vec3::new(x, y, z))
+    }
+
+    fn expected() -> &'static str {
+        "an array of three numbers"
+    }
+}
+
+impl FromValue for Rgb {
+    fn from_value(value: &Value) -> Option<Rgb> {
+        triple(value).map(|(r, g, b)| Rgb::new(r, g, b))
+    }
+
+    fn expected() -> &'static str {
+        "an array of three numbers"
+    }
+}
+
+impl FromValue for Triangle {
+    fn from_value(value: &Value) -> Option<Triangle> {
+        match value.as_slice() {
+            Some(values) if values.len() == 3 => {
+                match (Vec3::from_value(&values[0]),
+                       Vec3::from_value(&values[1]),
+                       Vec3::from_value(&values[2])) {
+                    (Some(a), Some(b), Some(c)) => Some(Triangle::new(a, b, c)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn expected() -> &'static str {
+        "an array of three points"
+    }
+}
+
+struct Section<'a> {
+    table: &'a Table,
+    path: String,
+}
+
+impl<'a> Section<'a> {
+    fn key(&self, key: &str) -> String {
+        if self.path.is_empty() {
+            key.to_owned()
+        } else {
+            format!("{}.{}", self.path, key)
+        }
+    }
+
+    fn invalid<T>(&self, key: &str, message: String) -> Result<T> {
+        Err(Error::Invalid {
+            key: self.key(key),
+            message: message,
+        })
+    }
+
+    fn get<T: FromValue>(&self, key: &str) -> Result<Option<T>> {
+        match self.table.get(key) {
+            Some(value) => {
+                match T::from_value(value) {
+                    Some(value) => Ok(Some(value)),
+                    None => self.invalid(key, format!("Expected {}", T::expected())),
+                }
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn require<T: FromValue>(&self, key: &str) -> Result<T> {
+        match try!(self.get(key)) {
+            Some(value) => Ok(value),
+            None => self.invalid(key, "Missing value".to_owned()),
+        }
+    }
+
+    fn list<T: FromValue>(&self, key: &str) -> Result<Vec<T>> {
+        match self.table.get(key) {
+            Some(&Value::Array(ref values)) => {
+                values.iter()
+                      .enumerate()
+                      .map(|(index, value)| {
+                          match T::from_value(value) {
+                              Some(value) => Ok(value),
+                              None => {
+                                  self.invalid(&format!("{}[{}]", key, index),
+                                               format!("Expected {}", T::expected()))
+                              }
+                          }
+                      })
+                      .collect()
+            }
+            Some(_) => self.invalid(key, "Expected an array".to_owned()),
+            None => self.invalid(key, "Missing value".to_owned()),
+        }
+    }
+
+    fn section(&self, key: &str) -> Result<Option<Section<'a>>> {
+        match self.table.get(key) {
+            Some(&Value::Table(ref table)) => {
+                Ok(Some(Section {
+                    table: table,
+                    path: self.key(key),
+                }))
+            }
+            Some(_) => self.invalid(key, "Expected a table".to_owned()),
+            None => Ok(None),
+        }
+    }
+
+    fn sections(&self, key: &str) -> Result<Vec<Section<'a>>> {
+        match self.table.get(key) {
+            Some(&Value::Array(ref values)) => {
+                values.iter()
+                      .enumerate()
+                      .map(|(index, value)| {
+                          let key = format!("{}[{}]", key, index);
+                          match *value {
+                              Value::Table(ref table) => {
+                                  Ok(Section {
+                                      table: table,
+                                      path: self.key(&key),
+                                  })
+                              }
+                              _ => self.invalid(&key, "Expected a table".to_owned()),
+                          }
+                      })
+                      .collect()
+            }
+            Some(_) => self.invalid(key, "Expected an array of tables".to_owned()),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+fn parse_material(section: &Section) -> Result<Material> {
+    let kind: String = try!(section.require("type"));
+    let material: Material = match &kind[..] {
+        "lambert" => Arc::new(Lambert::new(try!(section.require("albedo")))),
+        "blinn_phong" => Arc::new(BlinnPhong::new(try!(section.require("n")))),
+        "unlimited_chromatic" => {
+            Arc::new(UnlimitedChromatic::new(try!(section.require("kd")),
+                                             try!(section.require("ks")),
+                                             try!(section.require("roughness"))))
+        }
+        "broken" => {
+            let emissive = try!(section.get("emissive")).unwrap_or(Rgb::new(0.0, 0.0, 0.0));
+            Arc::new(Broken::new(try!(section.require("albedo")),
+                                 try!(section.require("reflectivity")),
+                                 try!(section.require("roughness")),
+                                 emissive))
+        }
+        _ => return section.invalid("type", format!("Unknown BRDF type: {}", kind)),
+    };
+    Ok(material)
+}
+
+fn material_from_obj(material: &obj::Material) -> Material {
+    // Approximate the roughness from the Phong exponent if the material
+    // doesn't specify it directly
+    let roughness = material.roughness
+                            .unwrap_or_else(|| f32::sqrt(2.0 / (material.shininess + 2.0)));
+    Arc::new(UnlimitedChromatic::new(material.diffuse, material.specular, roughness))
+}
+
+fn parse_camera(section: &Section) -> Result<Camera> {
+    let width: usize = try!(section.require("width"));
+    if width == 0 {
+        return section.invalid("width", "Expected at least one pixel".to_owned());
+    }
+    let height: usize = try!(section.require("height"));
+    if height == 0 {
+        return section.invalid("height", "Expected at least one pixel".to_owned());
+    }
+    let position = try!(section.get("position")).unwrap_or(Vec3::new(0.0, 0.0, 0.0));
+    let field_of_view: f32 = try!(section.get("field_of_view")).unwrap_or(90.0);
+    let mut camera = Camera::new((width, height), position, field_of_view.to_radians());
+
+    if let Some(projection) = try!(section.get::<String>("projection")) {
+        camera.projection = match &projection[..] {
+            "perspective" => Projection::Perspective,
+            "orthographic" => {
+                Projection::Orthographic { height: try!(section.require("view_height")) }
+            }
+            "equirectangular" => Projection::Equirectangular,
+            "fisheye" => Projection::Fisheye,
+            _ => {
+                return section.invalid("projection",
+                                       format!("Unknown projection: {}", projection))
+            }
+        };
+    }
+
+    // The camera either looks at a target or gets turned by angles in degrees
+    let target = try!(section.get("look_at"));
+    if let Some(target) = target {
+        let up = try!(section.get("up")).unwrap_or(Vec3::new(0.0, 1.0, 0.0));
+        camera.look_at(target, up);
+    } else {
+        let yaw: f32 = try!(section.get("yaw")).unwrap_or(0.0);
+        let pitch: f32 = try!(section.get("pitch")).unwrap_or(0.0);
+        camera.set_yaw(yaw.to_radians());
+        camera.set_pitch(pitch.to_radians());
+    }
+    if let Some(roll) = try!(section.get::<f32>("roll")) {
+        camera.set_roll(roll.to_radians());
+    }
+
+    camera.aperture_radius = try!(section.get("aperture_radius")).unwrap_or(0.0);
+    // Whatever the camera looks at is in focus by default
+    camera.focal_distance = match (try!(section.get("focal_distance")), target) {
+        (Some(focal_distance), _) => focal_distance,
+        (None, Some(target)) => na::norm(&(target - position)),
+        (None, None) => 1.0,
+    };
+    let blades = try!(section.get("aperture_blades")).unwrap_or(0);
+    if blades > 0 {
+        if blades < 3 {
+            return section.invalid("aperture_blades", "Expected at least 3 blades".to_owned());
+        }
+        let rotation: f32 = try!(section.get("aperture_rotation")).unwrap_or(0.0);
+        camera.aperture = Aperture::Polygonal {
+            blades: blades,
+            rotation: rotation.to_radians(),
+        };
+    }
+
+    Ok(camera)
+}
+
+fn parse_sampling(section: Option<Section>) -> Result<SamplingConfig> {
+    let section = match section {
+        Some(section) => section,
+        None => return Ok(SamplingConfig::path_tracing(3, 64)),
+    };
+    let mode = try!(section.get::<String>("mode")).unwrap_or("path_tracing".to_owned());
+    match &mode[..] {
+        "path_tracing" => {
+            Ok(SamplingConfig::path_tracing(try!(section.get("min_depth")).unwrap_or(3),
+                                            try!(section.get("max_depth")).unwrap_or(64)))
+        }
+        "branching" => {
+            Ok(SamplingConfig::new(try!(section.get("max_depth")).unwrap_or(5),
+                                   try!(section.get("starting_samples")).unwrap_or(1),
+                                   try!(section.get("scale_factor")).unwrap_or(1.0)))
+        }
+        _ => section.invalid("mode", format!("Unknown sampling mode: {}", mode)),
+    }
+}
+
+fn parse_adaptive_sampling(section: Option<Section>) -> Result<Option<AdaptiveSampling>> {
+    let section = match section {
+        Some(section) => section,
+        None => return Ok(None),
+    };
+    let threshold = match try!(section.get("adaptive_threshold")) {
+        Some(threshold) => threshold,
+        None => return Ok(None),
+    };
+    Ok(Some(AdaptiveSampling {
+        threshold: threshold,
+        min_samples: try!(section.get::<usize>("min_samples")).unwrap_or(16) as u32,
+    }))
+}
+
+fn parse_sampler(section: Option<Section>) -> Result<Box<Sampler + Send>> {
+    let section = match section {
+        Some(section) => section,
+        None => return Ok(Box::new(Independent::new())),
+    };
+    let kind = try!(section.get::<String>("sampler")).unwrap_or("independent".to_owned());
+    let sampler: Box<Sampler + Send> = match &kind[..] {
+        "independent" => Box::new(Independent::new()),
+        "stratified" => {
+            let strata = try!(section.get("strata")).unwrap_or(16);
+            if strata == 0 {
+                return section.invalid("strata", "Expected at least one stratum".to_owned());
+            }
+            Box::new(Stratified::new(strata as u32))
+        }
+        "halton" => Box::new(Halton::new()),
+        "sobol" => Box::new(Sobol::new()),
+        "blue_noise" => Box::new(BlueNoise::new()),
+        _ => return section.invalid("sampler", format!("Unknown sampler: {}", kind)),
+    };
+    Ok(sampler)
+}
+
+fn parse_tone_mapper(section: &Section) -> Result<ToneMapper> {
+    let operator = match try!(section.get::<String>("tone_mapping")) {
+        None => Operator::Clamp,
+        Some(operator) => {
+            match &operator[..] {
+                "clamp" => Operator::Clamp,
+                "reinhard" => Operator::Reinhard,
+                "extended_reinhard" => {
+                    Operator::ExtendedReinhard { white: try!(section.get("white")).unwrap_or(4.0) }
+                }
+                "aces" => Operator::Aces,
+                "agx" => Operator::AgX,
+                _ => {
+                    return section.invalid("tone_mapping",
+                                           format!("Unknown tone mapping operator: {}", operator))
+                }
+            }
+        }
+    };
+
+    let transfer = match try!(section.get::<String>("transfer")) {
+        Some(ref transfer) if transfer == "srgb" => Transfer::Srgb,
+        Some(ref transfer) if transfer != "gamma" => {
+            return section.invalid("transfer", format!("Unknown transfer function: {}", transfer))
+        }
+        _ => Transfer::Gamma(try!(section.get("gamma")).unwrap_or(2.2)),
+    };
+
+    let exposure = try!(section.get("exposure")).unwrap_or(0.0);
+
+    Ok(ToneMapper::new(operator, exposure, transfer))
+}
+
+fn parse_settings(section: Option<Section>) -> Result<RenderSettings> {
+    let mut settings = RenderSettings {
+        passes: 500,
+        seed: 0,
+        tile_size: 32,
+        tile_order: TileOrder::Scanline,
+        tone_mapper: ToneMapper::new(Operator::Clamp, 0.0, Transfer::Gamma(2.2)),
+        output: PathBuf::from("rendered.png"),
+        sample_counts: None,
+        aovs: Vec::new(),
+        separate_aovs: false,
+        denoise: false,
+    };
+    if let Some(section) = section {
+        if let Some(passes) = try!(section.get("passes")) {
+            settings.passes = passes;
+        }
+        if let Some(seed) = try!(section.get::<usize>("seed")) {
+            settings.seed = seed as u64;
+        }
+        if let Some(tile_size) = try!(section.get("tile_size")) {
+            if tile_size == 0 {
+                return section.invalid("tile_size", "Expected at least one pixel".to_owned());
+            }
+            settings.tile_size = tile_size;
+        }
+        if let Some(order) = try!(section.get::<String>("tile_order")) {
+            settings.tile_order = match &order[..] {
+                "scanline" => TileOrder::Scanline,
+                "spiral" => TileOrder::Spiral,
+                "hilbert" => TileOrder::Hilbert,
+                _ => return section.invalid("tile_order", format!("Unknown tile order: {}", order)),
+            };
+        }
+        settings.tone_mapper = try!(parse_tone_mapper(&section));
+        if let Some(output) = try!(section.get::<String>("output")) {
+            settings.output = PathBuf::from(output);
+        }
+        if let Some(sample_counts) = try!(section.get::<String>("sample_counts")) {
+            settings.sample_counts = Some(PathBuf::from(sample_counts));
+        }
+        if section.table.contains_key("aovs") {
+            settings.aovs = try!(section.list("aovs"));
+        }
+        if let Some(separate_aovs) = try!(section.get("separate_aovs")) {
+            settings.separate_aovs = separate_aovs;
+        }
+        if let Some(denoise) = try!(section.get("denoise")) {
+            settings.denoise = denoise;
+        }
+    }
+    Ok(settings)
+}
+
+/// Scales and rotates the entity around the origin of its object space
+/// and moves it by the translation afterwards.
+fn parse_transform(section: &Section) -> Result<Transform> {
+    let scale = match section.table.get("scale") {
+        Some(&Value::Array(_)) => try!(section.require("scale")),
+        Some(_) => {
+            let scale: f32 = try!(section.require("scale"));
+            Vec3::new(scale, scale, scale)
+        }
+        None => Vec3::new(1.0, 1.0, 1.0),
+    };
+    if scale.x == 0.0 || scale.y == 0.0 || scale.z == 0.0 {
+        return section.invalid("scale", "Can't scale by 0".to_owned());
+    }
+
+    let mut transform = Transform::from_scale(scale);
+    if let Some(angle) = try!(section.get::<f32>("rotation")) {
+        let axis = try!(section.get("rotation_axis")).unwrap_or(Vec3::new(0.0, 1.0, 0.0));
+        if na::norm(&axis) == 0.0 {
+            return section.invalid("rotation_axis", "Expected a non-zero axis".to_owned());
+        }
+        transform = transform.then(&Transform::from_rotation(axis, angle.to_radians()));
+    }
+    Ok(transform)
+}
+
+/// Puts the entity's own transform between the scaling and rotation of the
+/// scene file and its translation.
+fn place<T: Entity>(mut entity: T, local: &Transform, translation: Vec3) -> T {
+    let transform = local.then(entity.transform())
+                         .then(&Transform::from_translation(translation));
+    entity.set_transform(transform);
+    entity
+}
+
+fn add_entity(raytracer: &mut RayTracer,
+              section: &Section,
+              directory: &Path)
+              -> Result<()> {
+    let local = try!(parse_transform(section));
+    let translation = try!(section.get("translation")).unwrap_or(Vec3::new(0.0, 0.0, 0.0));
+
+    let material = match try!(section.section("brdf")) {
+        Some(brdf) => Some(try!(parse_material(&brdf))),
+        None => None,
+    };
+
+    let kind: String = try!(section.require("type"));
+    match &kind[..] {
+        "sphere" => {
+            let material = match material {
+                Some(material) => material,
+                None => return section.invalid("brdf", "Missing table".to_owned()),
+            };
+            let sphere = Sphere::new(try!(section.require("center")),
+                                     try!(section.require("radius")),
+                                     material);
+            raytracer.add_entity(place(sphere, &local, translation));
+        }
+        "mesh" => {
+            if let Some(file) = try!(section.get::<String>("file")) {
+                // The BRDF overrides the materials from the MTL libraries
+                let meshes = match material {
+                    Some(material) => try!(obj::load(directory.join(&file), |_| material.clone())),
+                    None => try!(obj::load(directory.join(&file), material_from_obj)),
+                };
+                for mesh in meshes {
+                    raytracer.add_entity(place(mesh, &local, translation));
+                }
+            } else {
+                let material = match material {
+                    Some(material) => material,
+                    None => return section.invalid("brdf", "Missing table".to_owned()),
+                };
+                let triangles = try!(section.list("triangles"));
+                raytracer.add_entity(place(Mesh::new(triangles, material), &local, translation));
+            }
+        }
+        _ => return section.invalid("type", format!("Unknown entity type: {}", kind)),
+    }
+
+    Ok(())
+}
+
+fn add_light(raytracer: &mut RayTracer, section: &Section) -> Result<()> {
+    let kind: String = try!(section.require("type"));
+    match &kind[..] {
+        "point" => {
+            raytracer.add_light(PointLight::new(try!(section.require("position")),
+                                                try!(section.require("intensity"))));
+        }
+        "directional" => {
+            raytracer.add_light(DirectionalLight::new(try!(section.require("direction")),
+                                                      try!(section.require("irradiance"))));
+        }
+        "spot" => {
+            let inner_angle: f32 = try!(section.require("inner_angle"));
+            let outer_angle: f32 = try!(section.require("outer_angle"));
+            raytracer.add_light(SpotLight::new(try!(section.require("position")),
+                                               try!(section.require("direction")),
+                                               try!(section.require("intensity")),
+                                               inner_angle.to_radians(),
+                                               outer_angle.to_radians()));
+        }
+        "area" => {
+            raytracer.add_light(AreaLight::new(try!(section.require("corner")),
+                                               try!(section.require("edge_u")),
+                                               try!(section.require("edge_v")),
+                                               try!(section.require("radiance"))));
+        }
+        _ => return section.invalid("type", format!("Unknown light type: {}", kind)),
+    }
+
+    Ok(())
+}
+
+fn parse_environment(section: &Section, directory: &Path) -> Result<Box<Environment + Sync>> {
+    let kind: String = try!(section.require("type"));
+    let mut sun_direction = None;
+    let environment: Box<Environment + Sync> = match &kind[..] {
+        "constant" => Box::new(Constant::new(try!(section.require("radiance")))),
+        "gradient" => {
+            Box::new(Gradient::new(try!(section.require("bottom")),
+                                   try!(section.require("top"))))
+        }
+        "sky" => {
+            let direction = try!(section.require("sun_direction"));
+            sun_direction = Some(direction);
+            Box::new(Sky::new(direction,
+                              try!(section.get("turbidity")).unwrap_or(3.0),
+                              try!(section.get("scale")).unwrap_or(0.1)))
+        }
+        "map" => {
+            let file: String = try!(section.require("file"));
+            let intensity = try!(section.get("intensity")).unwrap_or(1.0);
+            match EnvironmentMap::open(directory.join(&file), intensity) {
+                Ok(map) => Box::new(map),
+                Err(error) => return section.invalid("file", format!("{}", error)),
+            }
+        }
+        _ => return section.invalid("type", format!("Unknown environment type: {}", kind)),
+    };
+
+    let sun = match try!(section.section("sun")) {
+        Some(sun) => sun,
+        None => return Ok(environment),
+    };
+
+    // The sun of a sky defaults to the direction the sky is lit from
+    let direction = match try!(sun.get("direction")).or(sun_direction) {
+        Some(direction) => direction,
+        None => return sun.invalid("direction", "Missing value".to_owned()),
+    };
+    let angular_radius: f32 = try!(sun.get("angular_radius")).unwrap_or(0.27);
+    Ok(Box::new(Sun::new(environment,
+                         direction,
+                         angular_radius.to_radians(),
+                         try!(sun.require("radiance")))))
+}
+
+/// Loads a scene description. Mesh files and environment maps are looked up
+/// relative to the directory of the scene file.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Scene> {
+    let path = path.as_ref();
+    let mut source = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut source));
+
+    let mut parser = Parser::new(&source);
+    let table = match parser.parse() {
+        Some(table) => table,
+        None => {
+            let error = &parser.errors[0];
+            let (line, column) = parser.to_linecol(error.lo);
+            return Err(Error::Syntax {
+                line: line + 1,
+                column: column + 1,
+                message: error.desc.clone(),
+            });
+        }
+    };
+
+    let root = Section {
+        table: &table,
+        path: String::new(),
+    };
+
+    let camera = match try!(root.section("camera")) {
+        Some(section) => try!(parse_camera(&section)),
+        None => return root.invalid("camera", "Missing table".to_owned()),
+    };
+    let sampling_config = try!(parse_sampling(try!(root.section("sampling"))));
+    let sampler = try!(parse_sampler(try!(root.section("sampling"))));
+    let adaptive_sampling = try!(parse_adaptive_sampling(try!(root.section("sampling"))));
+    let settings = try!(parse_settings(try!(root.section("render"))));
+
+    let mut raytracer = RayTracer::new(camera, sampling_config);
+    raytracer.set_sampler(sampler);
+    raytracer.set_adaptive_sampling(adaptive_sampling);
+    raytracer.set_seed(settings.seed);
+    raytracer.set_tile_size(settings.tile_size);
+    raytracer.set_tile_order(settings.tile_order);
+    for &aov in &settings.aovs {
+        raytracer.enable_aov(aov);
+    }
+    if settings.denoise {
+        raytracer.enable_aov(Aov::Albedo);
+        raytracer.enable_aov(Aov::Normal);
+    }
+    let directory = path.parent().unwrap_or(Path::new(""));
+
+    for section in try!(root.sections("entities")) {
+        try!(add_entity(&mut raytracer, &section, directory));
+    }
+
+    for section in try!(root.sections("lights")) {
+        try!(add_light(&mut raytracer, &section));
+    }
+
+    if let Some(section) = try!(root.section("environment")) {
+        raytracer.set_environment(try!(parse_environment(&section, directory)));
+    }
+
+    Ok(Scene {
+        raytracer: raytracer,
+        settings: settings,
+    })
+}
@@ -0,0 +1,97 @@
+use Vec3;
+use ray::Ray;
+use nalgebra as na;
+use std::f32;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Aabb {
+            min: min,
+            max: max,
+        }
+    }
+
+    pub fn empty() -> Self {
+        Aabb::new(Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+                  Vec3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn grow(&self, point: Vec3) -> Aabb {
+        Aabb::new(na::inf(&self.min, &point), na::sup(&self.max, &point))
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(na::inf(&self.min, &other.min),
+                  na::sup(&self.max, &other.max))
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn extent(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn largest_axis(&self) -> usize {
+        let extent = self.extent();
+        if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let extent = self.extent();
+        2.0 * (extent.x * extent.y + extent.y * extent.z + extent.z * extent.x)
+    }
+
+    /// Slab test. Returns the distance at which the ray enters the box, as long
+    /// as that happens before `max_distance`.
+    pub fn intersects(&self, ray: &Ray, inv_direction: &Vec3, max_distance: f32) -> Option<f32> {
+        let mut t_min = 0.0;
+        let mut t_max = max_distance;
+
+        for axis in 0..3 {
+            let t0 = (self.min[axis] - ray.origin[axis]) * inv_direction[axis];
+            let t1 = (self.max[axis] - ray.origin[axis]) * inv_direction[axis];
+            let (t0, t1) = if t0 > t1 {
+                (t1, t0)
+            } else {
+                (t0, t1)
+            };
+            // Written so that a NaN from 0 * inf leaves the interval untouched
+            t_min = if t0 > t_min {
+                t0
+            } else {
+                t_min
+            };
+            t_max = if t1 < t_max {
+                t1
+            } else {
+                t_max
+            };
+            if t_min > t_max {
+                return None;
+            }
+        }
+
+        Some(t_min)
+    }
+}
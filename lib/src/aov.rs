@@ -0,0 +1,162 @@
+//! Arbitrary output variables, which are images of other quantities than the
+//! radiance that get rendered alongside it, mostly for compositing.
+
+use prelude::*;
+use exr::{self, Channel};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Aov {
+    /// The albedo of the first surface the camera sees.
+    Albedo,
+    /// The world space shading normal, facing the camera.
+    Normal,
+    /// The distance from the camera, 0 where the camera sees nothing.
+    Depth,
+    /// The world space position.
+    Position,
+    /// One more than the index of the entity, 0 where there is none.
+    ObjectId,
+    /// One more than the index of the BRDF among all the different BRDFs of
+    /// the entities, 0 where there is none.
+    MaterialId,
+    /// Light that reached the camera after a single bounce.
+    Direct,
+    /// Light that reached the camera after more than one bounce.
+    Indirect,
+    /// Light that reached the camera straight from its source, including
+    /// the environment.
+    Emission,
+}
+
+impl Aov {
+    pub fn all() -> [Aov; 9] {
+        [Aov::Albedo,
+         Aov::Normal,
+         Aov::Depth,
+         Aov::Position,
+         Aov::ObjectId,
+         Aov::MaterialId,
+         Aov::Direct,
+         Aov::Indirect,
+         Aov::Emission]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect",
+            Aov::Emission => "emission",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Aov> {
+        Aov::all().iter().cloned().find(|aov| aov.name() == name)
+    }
+
+    /// IDs can't be averaged, so they only come from the first sample of
+    /// every pixel. Everything else is the mean of all the samples.
+    pub fn is_averaged(&self) -> bool {
+        match *self {
+            Aov::ObjectId | Aov::MaterialId => false,
+            _ => true,
+        }
+    }
+
+    /// The channels the AOV is stored in. Colors are stored in red, green
+    /// and blue, vectors in x, y and z and everything else in the red
+    /// channel only.
+    fn channel_names(&self) -> &'static [&'static str] {
+        match *self {
+            Aov::Normal | Aov::Position => &["X", "Y", "Z"],
+            Aov::Depth => &["Z"],
+            Aov::ObjectId | Aov::MaterialId => &["id"],
+            _ => &["R", "G", "B"],
+        }
+    }
+
+    /// Splits the pixels of the AOV up into the channels of an OpenEXR
+    /// layer. The unnamed layer gets channels without any prefix.
+    pub fn exr_channels(&self, layer: &str, pixels: &[Rgb]) -> Vec<Channel> {
+        let names = self.channel_names();
+        if names[0] == "R" {
+            return exr::rgb_layer(layer, pixels);
+        }
+
+        let prefix = if layer.is_empty() {
+            String::new()
+        } else {
+            format!("{}.", layer)
+        };
+        names.iter()
+             .enumerate()
+             .map(|(index, name)| {
+                 let values = pixels.iter()
+                                    .map(|pixel| {
+                                        match index {
+                                            0 => pixel.red,
+                                            1 => pixel.green,
+                                            _ => pixel.blue,
+                                        }
+                                    })
+                                    .collect();
+                 Channel::new(format!("{}{}", prefix, name), values)
+             })
+             .collect()
+    }
+}
+
+/// What a single camera sample contributes to each of the AOVs.
+#[derive(Clone, Copy, Debug)]
+pub struct AovSample {
+    pub albedo: Rgb,
+    pub normal: Vec3,
+    pub depth: f32,
+    pub position: Vec3,
+    pub object_id: u32,
+    pub material_id: u32,
+    pub direct: Rgb,
+    pub indirect: Rgb,
+    pub emission: Rgb,
+}
+
+impl AovSample {
+    /// A sample that didn't hit anything and didn't receive any light.
+    pub fn new() -> Self {
+        let zero = Vec3::new(0.0, 0.0, 0.0);
+        let black = Rgb::new(0.0, 0.0, 0.0);
+        AovSample {
+            albedo: black,
+            normal: zero,
+            depth: 0.0,
+            position: zero,
+            object_id: 0,
+            material_id: 0,
+            direct: black,
+            indirect: black,
+            emission: black,
+        }
+    }
+
+    /// The value of an AOV, packed into a color.
+    pub fn value(&self, aov: Aov) -> Rgb {
+        let vector = |v: Vec3| Rgb::new(v.x, v.y, v.z);
+        let scalar = |value: f32| Rgb::new(value, value, value);
+        match aov {
+            Aov::Albedo => self.albedo,
+            Aov::Normal => vector(self.normal),
+            Aov::Depth => scalar(self.depth),
+            Aov::Position => vector(self.position),
+            Aov::ObjectId => scalar(self.object_id as f32),
+            Aov::MaterialId => scalar(self.material_id as f32),
+            Aov::Direct => self.direct,
+            Aov::Indirect => self.indirect,
+            Aov::Emission => self.emission,
+        }
+    }
+}
@@ -2,6 +2,7 @@ use prelude::*;
 use nalgebra as na;
 use std::f32;
 
+#[derive(Clone, Debug)]
 pub struct BlinnPhong {
     n: f32,
 }
@@ -1,8 +1,10 @@
 use {Rgb, Vec3};
 use nalgebra as na;
 use std::f32;
-use super::Brdf;
+use sampler::Sampler;
+use super::{Brdf, sampling};
 
+#[derive(Clone, Debug)]
 pub struct Broken {
     albedo: Rgb,
     reflectivity: f32,
@@ -19,6 +21,15 @@ impl Broken {
             emissive: emissive,
         }
     }
+
+    fn alpha(&self) -> f32 {
+        self.roughness * self.roughness
+    }
+
+    fn specular_probability(&self) -> f32 {
+        let albedo = (self.albedo.red + self.albedo.green + self.albedo.blue) / 3.0;
+        sampling::specular_probability(self.reflectivity, albedo * (1.0 - self.reflectivity))
+    }
 }
 
 impl Brdf for Broken {
@@ -84,4 +95,25 @@ impl Brdf for Broken {
     fn solve_emissive(&self) -> Rgb {
         self.emissive
     }
+
+    fn albedo(&self) -> Rgb {
+        self.albedo
+    }
+
+    fn sample(&self, v: Vec3, n: Vec3, sampler: &mut Sampler) -> (Vec3, f32, Rgb) {
+        let u1 = sampler.next();
+        let (u2, u3) = sampler.next_2d();
+        let l = sampling::glossy(v,
+                                 n,
+                                 self.alpha(),
+                                 self.specular_probability(),
+                                 u1,
+                                 u2,
+                                 u3);
+        (l, self.pdf(l, n, v), self.solve(l, n, v))
+    }
+
+    fn pdf(&self, l: Vec3, n: Vec3, v: Vec3) -> f32 {
+        sampling::glossy_pdf(l, n, v, self.alpha(), self.specular_probability())
+    }
 }
@@ -1,6 +1,7 @@
 use prelude::*;
 use std::f32::consts::PI;
 
+#[derive(Clone, Debug)]
 pub struct Lambert {
     albedo: Rgb,
 }
@@ -19,4 +20,8 @@ impl Brdf for Lambert {
     fn solve_emissive(&self) -> Rgb {
         Rgb::new(0.0, 0.0, 0.0)
     }
+
+    fn albedo(&self) -> Rgb {
+        self.albedo
+    }
 }
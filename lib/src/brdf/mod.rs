@@ -1,10 +1,98 @@
 use {Rgb, Vec3};
+use sampler::Sampler;
+use std::mem;
+use std::sync::Arc;
 
 pub trait Brdf {
     fn solve(&self, l: Vec3, n: Vec3, v: Vec3) -> Rgb;
     fn solve_emissive(&self) -> Rgb;
+
+    /// Picks a light direction with a probability density that should
+    /// roughly follow the shape of the BRDF. Returns the direction, its
+    /// density and the value of the BRDF for it. By default the directions
+    /// are distributed cosine weighted over the hemisphere.
+    fn sample(&self, v: Vec3, n: Vec3, sampler: &mut Sampler) -> (Vec3, f32, Rgb) {
+        let (u1, u2) = sampler.next_2d();
+        let l = sampling::cosine_hemisphere(n, u1, u2);
+        (l, self.pdf(l, n, v), self.solve(l, n, v))
+    }
+
+    /// The probability density of `sample` returning the light direction `l`.
+    fn pdf(&self, l: Vec3, n: Vec3, _v: Vec3) -> f32 {
+        sampling::cosine_hemisphere_pdf(l, n)
+    }
+
+    /// The overall color of the surface, without any lighting. White unless
+    /// the BRDF knows better.
+    fn albedo(&self) -> Rgb {
+        Rgb::new(1.0, 1.0, 1.0)
+    }
+
+    /// Tells BRDFs apart. Surfaces that share a BRDF, even through separate
+    /// `Box`es or `Arc`s, get the same identity. BRDFs without any fields
+    /// have none, as all of them live at the same address.
+    fn identity(&self) -> Option<usize> {
+        if mem::size_of_val(self) == 0 {
+            None
+        } else {
+            Some(self as *const Self as *const u8 as usize)
+        }
+    }
+}
+
+impl<T: Brdf + ?Sized> Brdf for Box<T> {
+    fn solve(&self, l: Vec3, n: Vec3, v: Vec3) -> Rgb {
+        (**self).solve(l, n, v)
+    }
+
+    fn solve_emissive(&self) -> Rgb {
+        (**self).solve_emissive()
+    }
+
+    fn sample(&self, v: Vec3, n: Vec3, sampler: &mut Sampler) -> (Vec3, f32, Rgb) {
+        (**self).sample(v, n, sampler)
+    }
+
+    fn pdf(&self, l: Vec3, n: Vec3, v: Vec3) -> f32 {
+        (**self).pdf(l, n, v)
+    }
+
+    fn albedo(&self) -> Rgb {
+        (**self).albedo()
+    }
+
+    fn identity(&self) -> Option<usize> {
+        (**self).identity()
+    }
+}
+
+impl<T: Brdf + ?Sized> Brdf for Arc<T> {
+    fn solve(&self, l: Vec3, n: Vec3, v: Vec3) -> Rgb {
+        (**self).solve(l, n, v)
+    }
+
+    fn solve_emissive(&self) -> Rgb {
+        (**self).solve_emissive()
+    }
+
+    fn sample(&self, v: Vec3, n: Vec3, sampler: &mut Sampler) -> (Vec3, f32, Rgb) {
+        (**self).sample(v, n, sampler)
+    }
+
+    fn pdf(&self, l: Vec3, n: Vec3, v: Vec3) -> f32 {
+        (**self).pdf(l, n, v)
+    }
+
+    fn albedo(&self) -> Rgb {
+        (**self).albedo()
+    }
+
+    fn identity(&self) -> Option<usize> {
+        (**self).identity()
+    }
 }
 
+pub mod sampling;
 pub mod broken;
 pub mod lambert;
 pub mod unlimited_chromatic;
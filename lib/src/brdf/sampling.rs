@@ -0,0 +1,147 @@
+//! Building blocks for importance sampling BRDFs. Everything works on unit
+//! vectors pointing away from the surface and takes its random numbers as
+//! uniformly distributed values in [0, 1).
+
+use Vec3;
+use clamp::Clamp;
+use nalgebra as na;
+use std::f32;
+use std::f32::consts::PI;
+
+/// Builds two tangents that form an orthonormal basis together with `n`.
+/// See "Building an Orthonormal Basis, Revisited" by Duff et al.
+pub fn basis(n: Vec3) -> (Vec3, Vec3) {
+    let sign = if n.z >= 0.0 {
+        1.0
+    } else {
+        -1.0
+    };
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+    let tangent = Vec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x);
+    let bitangent = Vec3::new(b, sign + n.y * n.y * a, -n.y);
+    (tangent, bitangent)
+}
+
+pub fn to_world(local: Vec3, n: Vec3) -> Vec3 {
+    let (tangent, bitangent) = basis(n);
+    tangent * local.x + bitangent * local.y + n * local.z
+}
+
+pub fn to_local(v: Vec3, n: Vec3) -> Vec3 {
+    let (tangent, bitangent) = basis(n);
+    Vec3::new(na::dot(&v, &tangent), na::dot(&v, &bitangent), na::dot(&v, &n))
+}
+
+pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
+    n * (2.0 * na::dot(&v, &n)) - v
+}
+
+pub fn cosine_hemisphere(n: Vec3, u1: f32, u2: f32) -> Vec3 {
+    let r = f32::sqrt(u1);
+    let phi = 2.0 * PI * u2;
+    let local = Vec3::new(r * f32::cos(phi),
+                          r * f32::sin(phi),
+                          f32::sqrt(f32::max(0.0, 1.0 - u1)));
+    to_world(local, n)
+}
+
+pub fn cosine_hemisphere_pdf(l: Vec3, n: Vec3) -> f32 {
+    f32::max(0.0, na::dot(&n, &l)) / PI
+}
+
+/// The GGX normal distribution function. `alpha` controls the width of the
+/// distribution and is often the square of a perceptual roughness.
+pub fn ggx_d(n_dot_h: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+    alpha2 / (PI * d * d)
+}
+
+/// The Smith masking function for GGX.
+pub fn ggx_g1(n_dot_v: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    2.0 * n_dot_v / (n_dot_v + f32::sqrt(alpha2 + (1.0 - alpha2) * n_dot_v * n_dot_v))
+}
+
+/// Samples a microfacet normal from the distribution of normals that are
+/// visible from `v`. See "Sampling the GGX Distribution of Visible Normals"
+/// by Heitz.
+pub fn ggx_vndf(v: Vec3, n: Vec3, alpha: f32, u1: f32, u2: f32) -> Vec3 {
+    let v = to_local(v, n);
+
+    // Transform the view direction to the hemisphere configuration
+    let vh = na::normalize(&Vec3::new(alpha * v.x, alpha * v.y, v.z));
+
+    let length2 = vh.x * vh.x + vh.y * vh.y;
+    let t1 = if length2 > 0.0 {
+        Vec3::new(-vh.y, vh.x, 0.0) / f32::sqrt(length2)
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let t2 = na::cross(&vh, &t1);
+
+    // Sample the projected area of the hemisphere
+    let r = f32::sqrt(u1);
+    let phi = 2.0 * PI * u2;
+    let p1 = r * f32::cos(phi);
+    let p2 = r * f32::sin(phi);
+    let s = 0.5 * (1.0 + vh.z);
+    let p2 = (1.0 - s) * f32::sqrt(1.0 - p1 * p1) + s * p2;
+
+    let nh = t1 * p1 + t2 * p2 + vh * f32::sqrt(f32::max(0.0, 1.0 - p1 * p1 - p2 * p2));
+
+    // And back to the ellipsoid configuration
+    let h = na::normalize(&Vec3::new(alpha * nh.x, alpha * nh.y, f32::max(0.0, nh.z)));
+
+    to_world(h, n)
+}
+
+/// The density of the light directions you get from reflecting `v` on the
+/// normals sampled by `ggx_vndf`.
+pub fn ggx_vndf_pdf(l: Vec3, n: Vec3, v: Vec3, alpha: f32) -> f32 {
+    let n_dot_v = na::dot(&n, &v);
+    let n_dot_l = na::dot(&n, &l);
+    if n_dot_v <= 0.0 || n_dot_l <= 0.0 {
+        return 0.0;
+    }
+    let h = na::normalize(&(l + v));
+    let n_dot_h = na::dot(&n, &h).saturate();
+    ggx_d(n_dot_h, alpha) * ggx_g1(n_dot_v, alpha) / (4.0 * n_dot_v)
+}
+
+/// Samples a light direction for a BRDF that consists of a diffuse and a
+/// GGX specular lobe. The specular lobe gets picked with a probability of
+/// `specular_probability`, the diffuse lobe is sampled cosine weighted.
+pub fn glossy(v: Vec3,
+              n: Vec3,
+              alpha: f32,
+              specular_probability: f32,
+              u1: f32,
+              u2: f32,
+              u3: f32)
+              -> Vec3 {
+    if u1 < specular_probability {
+        let h = ggx_vndf(v, n, alpha, u2, u3);
+        reflect(v, h)
+    } else {
+        cosine_hemisphere(n, u2, u3)
+    }
+}
+
+pub fn glossy_pdf(l: Vec3, n: Vec3, v: Vec3, alpha: f32, specular_probability: f32) -> f32 {
+    specular_probability * ggx_vndf_pdf(l, n, v, alpha) +
+    (1.0 - specular_probability) * cosine_hemisphere_pdf(l, n)
+}
+
+/// Picks how often the specular lobe should be sampled based on the
+/// (average) weights of the lobes. Neither lobe is ever left out entirely,
+/// as the Fresnel term brightens up the specular lobe at grazing angles.
+pub fn specular_probability(specular_weight: f32, diffuse_weight: f32) -> f32 {
+    let sum = specular_weight + diffuse_weight;
+    if sum > 0.0 {
+        (specular_weight / sum).clamp(0.1, 0.9)
+    } else {
+        0.5
+    }
+}
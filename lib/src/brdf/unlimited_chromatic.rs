@@ -1,8 +1,11 @@
 use prelude::*;
+use super::sampling;
 use nalgebra as na;
+use sampler::Sampler;
 use std::f32;
 use std::f32::consts::PI;
 
+#[derive(Clone, Debug)]
 pub struct UnlimitedChromatic {
     kd: Rgb,
     ks: Rgb,
@@ -17,6 +20,15 @@ impl UnlimitedChromatic {
             roughness: roughness,
         }
     }
+
+    fn specular_probability(&self) -> f32 {
+        let diffuse = self.kd * (WHITE - self.ks);
+        sampling::specular_probability(average(self.ks), average(diffuse))
+    }
+}
+
+fn average(color: Rgb) -> f32 {
+    (color.red + color.green + color.blue) / 3.0
 }
 
 const WHITE: Rgb = Rgb {
@@ -78,4 +90,26 @@ impl Brdf for UnlimitedChromatic {
     fn solve_emissive(&self) -> Rgb {
         Rgb::new(0.0, 0.0, 0.0)
     }
+
+    /// The diffuse color plus the specular color seen head on.
+    fn albedo(&self) -> Rgb {
+        self.kd * (WHITE - self.ks) + self.ks
+    }
+
+    fn sample(&self, v: Vec3, n: Vec3, sampler: &mut Sampler) -> (Vec3, f32, Rgb) {
+        let u1 = sampler.next();
+        let (u2, u3) = sampler.next_2d();
+        let l = sampling::glossy(v,
+                                 n,
+                                 self.roughness,
+                                 self.specular_probability(),
+                                 u1,
+                                 u2,
+                                 u3);
+        (l, self.pdf(l, n, v), self.solve(l, n, v))
+    }
+
+    fn pdf(&self, l: Vec3, n: Vec3, v: Vec3) -> f32 {
+        sampling::glossy_pdf(l, n, v, self.roughness, self.specular_probability())
+    }
 }
@@ -0,0 +1,261 @@
+use Vec3;
+use aabb::Aabb;
+use ray::Ray;
+use collision::Collision;
+use std::cmp;
+use std::f32;
+
+const BIN_COUNT: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+const TRAVERSAL_COST: f32 = 1.0;
+const INTERSECTION_COST: f32 = 1.0;
+
+#[derive(Clone, Copy, Debug)]
+struct Node {
+    bounds: Aabb,
+    // Leaves: index of the first primitive in `indices`.
+    // Interior nodes: index of the second child, the first one directly follows the node.
+    offset: usize,
+    count: usize,
+    axis: usize,
+}
+
+impl Node {
+    fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+}
+
+/// Bounding volume hierarchy over a list of primitives, built with a binned
+/// surface area heuristic. The hierarchy only knows about the primitives'
+/// bounding boxes, so it refers to them by their index in the slice it was
+/// built from.
+pub struct Bvh {
+    nodes: Vec<Node>,
+    indices: Vec<usize>,
+    parents: Vec<usize>,
+    leaves: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn new(bounds: &[Aabb]) -> Self {
+        let mut bvh = Bvh {
+            nodes: Vec::new(),
+            indices: (0..bounds.len()).collect(),
+            parents: Vec::new(),
+            leaves: vec![0; bounds.len()],
+        };
+
+        if !bounds.is_empty() {
+            let centroids = bounds.iter().map(|b| b.centroid()).collect::<Vec<_>>();
+            bvh.build(bounds, &centroids, 0, bounds.len());
+            bvh.link();
+        }
+
+        bvh
+    }
+
+    fn link(&mut self) {
+        self.parents = vec![0; self.nodes.len()];
+
+        for (node_index, node) in self.nodes.iter().enumerate() {
+            if node.is_leaf() {
+                for &index in &self.indices[node.offset..node.offset + node.count] {
+                    self.leaves[index] = node_index;
+                }
+            } else {
+                self.parents[node_index + 1] = node_index;
+                self.parents[node.offset] = node_index;
+            }
+        }
+    }
+
+    /// Updates the hierarchy after the bounding box of a single primitive
+    /// changed, by refitting the nodes on the path from its leaf up to the
+    /// root. The tree's topology stays the same, so this is a lot cheaper than
+    /// a rebuild, but the quality of the hierarchy degrades the further
+    /// primitives move away from where they were when it was built.
+    pub fn refit(&mut self, bounds: &[Aabb], primitive: usize) {
+        let mut node_index = self.leaves[primitive];
+
+        loop {
+            let node_bounds = {
+                let node = &self.nodes[node_index];
+                if node.is_leaf() {
+                    self.indices[node.offset..node.offset + node.count]
+                        .iter()
+                        .fold(Aabb::empty(), |acc, &i| acc.union(&bounds[i]))
+                } else {
+                    self.nodes[node_index + 1].bounds.union(&self.nodes[node.offset].bounds)
+                }
+            };
+
+            self.nodes[node_index].bounds = node_bounds;
+
+            if node_index == 0 {
+                break;
+            }
+            node_index = self.parents[node_index];
+        }
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.nodes.first().map_or_else(Aabb::empty, |node| node.bounds)
+    }
+
+    fn build(&mut self, bounds: &[Aabb], centroids: &[Vec3], start: usize, end: usize) -> usize {
+        let node_index = self.nodes.len();
+        let count = end - start;
+
+        let node_bounds = self.indices[start..end]
+                              .iter()
+                              .fold(Aabb::empty(), |acc, &i| acc.union(&bounds[i]));
+
+        self.nodes.push(Node {
+            bounds: node_bounds,
+            offset: start,
+            count: count,
+            axis: 0,
+        });
+
+        if count == 1 {
+            return node_index;
+        }
+
+        let centroid_bounds = self.indices[start..end]
+                                  .iter()
+                                  .fold(Aabb::empty(), |acc, &i| acc.grow(centroids[i]));
+
+        let axis = centroid_bounds.largest_axis();
+        let min = centroid_bounds.min[axis];
+        let extent = centroid_bounds.max[axis] - min;
+
+        if extent <= 0.0 {
+            // All centroids coincide, so there's nothing to split on.
+            return node_index;
+        }
+
+        let bin_of = |centroid: &Vec3| {
+            let bin = ((centroid[axis] - min) / extent * BIN_COUNT as f32) as usize;
+            cmp::min(bin, BIN_COUNT - 1)
+        };
+
+        let mut bin_bounds = [Aabb::empty(); BIN_COUNT];
+        let mut bin_counts = [0; BIN_COUNT];
+
+        for &i in &self.indices[start..end] {
+            let bin = bin_of(&centroids[i]);
+            bin_bounds[bin] = bin_bounds[bin].union(&bounds[i]);
+            bin_counts[bin] += 1;
+        }
+
+        let mut right_areas = [0.0; BIN_COUNT];
+        let mut right_counts = [0; BIN_COUNT];
+        let mut accumulated = Aabb::empty();
+        let mut accumulated_count = 0;
+
+        for bin in (1..BIN_COUNT).rev() {
+            accumulated = accumulated.union(&bin_bounds[bin]);
+            accumulated_count += bin_counts[bin];
+            right_areas[bin] = accumulated.surface_area();
+            right_counts[bin] = accumulated_count;
+        }
+
+        let mut best_cost = f32::INFINITY;
+        let mut best_split = 0;
+        accumulated = Aabb::empty();
+        accumulated_count = 0;
+
+        for bin in 0..BIN_COUNT - 1 {
+            accumulated = accumulated.union(&bin_bounds[bin]);
+            accumulated_count += bin_counts[bin];
+
+            if accumulated_count == 0 || right_counts[bin + 1] == 0 {
+                continue;
+            }
+
+            let cost = TRAVERSAL_COST +
+                       INTERSECTION_COST *
+                       (accumulated.surface_area() * accumulated_count as f32 +
+                        right_areas[bin + 1] * right_counts[bin + 1] as f32) /
+                       node_bounds.surface_area();
+
+            if cost < best_cost {
+                best_cost = cost;
+                best_split = bin + 1;
+            }
+        }
+
+        let leaf_cost = INTERSECTION_COST * count as f32;
+
+        if best_split == 0 || (count <= MAX_LEAF_SIZE && best_cost >= leaf_cost) {
+            return node_index;
+        }
+
+        let mut mid = start;
+        for i in start..end {
+            if bin_of(&centroids[self.indices[i]]) < best_split {
+                self.indices.swap(i, mid);
+                mid += 1;
+            }
+        }
+
+        self.build(bounds, centroids, start, mid);
+        let second = self.build(bounds, centroids, mid, end);
+
+        let node = &mut self.nodes[node_index];
+        node.offset = second;
+        node.count = 0;
+        node.axis = axis;
+
+        node_index
+    }
+
+    /// Finds the nearest collision along the ray. `hit` gets called with the
+    /// index of every primitive whose bounding box might contain a closer hit
+    /// than the ones found so far.
+    pub fn closest_hit<'a, F>(&self, ray: &Ray, mut hit: F) -> Option<Collision<'a>>
+        where F: FnMut(usize) -> Option<Collision<'a>>
+    {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let inv_direction = Vec3::new(1.0 / ray.direction.x,
+                                      1.0 / ray.direction.y,
+                                      1.0 / ray.direction.z);
+
+        let mut closest: Option<Collision> = None;
+        let mut max_distance = f32::INFINITY;
+        let mut stack = Vec::with_capacity(32);
+        stack.push(0);
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+
+            if node.bounds.intersects(ray, &inv_direction, max_distance).is_none() {
+                continue;
+            }
+
+            if node.is_leaf() {
+                for &index in &self.indices[node.offset..node.offset + node.count] {
+                    closest = Collision::closest(closest, hit(index));
+                    if let Some(ref collision) = closest {
+                        max_distance = collision.distance;
+                    }
+                }
+            } else {
+                // Visit the child on the near side of the split first.
+                let (near, far) = if ray.direction[node.axis] < 0.0 {
+                    (node.offset, node_index + 1)
+                } else {
+                    (node_index + 1, node.offset)
+                };
+                stack.push(far);
+                stack.push(near);
+            }
+        }
+
+        closest
+    }
+}
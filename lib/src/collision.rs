@@ -1,18 +1,50 @@
 use brdf::Brdf;
 use Vec3;
 
-pub struct Collision<'brdf, BrdfType: Brdf + 'static> {
+pub struct Collision<'brdf> {
+    pub distance: f32,
     pub position: Vec3,
     pub normal: Vec3,
-    pub brdf: &'brdf BrdfType,
+    pub brdf: &'brdf Brdf,
+    /// The index of the entity that was hit, as it was added to the ray
+    /// tracer.
+    pub entity: usize,
+    /// Where a triangle was hit, as the weights of its second and third
+    /// vertex. The first one gets the rest.
+    pub barycentrics: Option<(f32, f32)>,
+    /// The texture coordinates of the hit, if the surface has any.
+    pub uv: Option<(f32, f32)>,
+    /// The direction the u texture coordinate increases in, if the surface
+    /// has tangents.
+    pub tangent: Option<Vec3>,
 }
 
-impl<'a, BrdfType: Brdf + 'static> Collision<'a, BrdfType> {
-    pub fn new(position: Vec3, normal: Vec3, brdf: &'a BrdfType) -> Self {
+impl<'a> Collision<'a> {
+    pub fn new(distance: f32, position: Vec3, normal: Vec3, brdf: &'a Brdf) -> Self {
         Collision {
+            distance: distance,
             position: position,
             normal: normal,
             brdf: brdf,
+            entity: 0,
+            barycentrics: None,
+            uv: None,
+            tangent: None,
+        }
+    }
+
+    /// Returns whichever of the two collisions is closer to the ray origin.
+    pub fn closest(a: Option<Self>, b: Option<Self>) -> Option<Self> {
+        match (a, b) {
+            (Some(a), Some(b)) => {
+                if b.distance < a.distance {
+                    Some(b)
+                } else {
+                    Some(a)
+                }
+            }
+            (a, None) => a,
+            (None, b) => b,
         }
     }
 }
@@ -0,0 +1,107 @@
+//! Smooths out the noise of a rendered image with a joint bilateral filter,
+//! which only averages pixels that show similar surfaces according to the
+//! albedo and normal AOVs.
+
+use prelude::*;
+use image::Image;
+use tone_mapping;
+use std::f32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Denoiser {
+    /// The standard deviation of the spatial Gaussian in pixels. The filter
+    /// reaches twice as far.
+    pub sigma_spatial: f32,
+    pub sigma_albedo: f32,
+    pub sigma_normal: f32,
+    /// How many standard errors of its own luminance the luminance of a
+    /// neighbour may be off by, which keeps shadows and highlights sharp.
+    /// Pixels with fewer than two samples don't have a standard error and
+    /// only get guided by the AOVs.
+    pub sigma_luminance: f32,
+}
+
+fn squared_distance(a: Rgb, b: Rgb) -> f32 {
+    let d = a - b;
+    d.red * d.red + d.green * d.green + d.blue * d.blue
+}
+
+impl Denoiser {
+    pub fn new() -> Self {
+        Denoiser {
+            sigma_spatial: 2.0,
+            sigma_albedo: 0.1,
+            sigma_normal: 0.2,
+            sigma_luminance: 2.0,
+        }
+    }
+
+    /// Filters the image, keeping its sample counts and variance. The albedo
+    /// and normal images have to be as large as the image.
+    pub fn denoise(&self, image: &Image, albedo: &Image, normal: &Image) -> Image {
+        assert!(image.dimensions == albedo.dimensions && image.dimensions == normal.dimensions);
+        let (width, height) = image.dimensions;
+        let colors = image.pixels();
+        let albedos = albedo.pixels();
+        let normals = normal.pixels();
+
+        let radius = f32::ceil(2.0 * self.sigma_spatial) as isize;
+        let spatial_factor = -1.0 / (2.0 * self.sigma_spatial * self.sigma_spatial);
+        let spatial_weights: Vec<f32> = (-radius..radius + 1)
+                                            .map(|offset| {
+                                                let offset = offset as f32;
+                                                f32::exp(spatial_factor * offset * offset)
+                                            })
+                                            .collect();
+        let albedo_factor = -1.0 / (2.0 * self.sigma_albedo * self.sigma_albedo);
+        let normal_factor = -1.0 / (2.0 * self.sigma_normal * self.sigma_normal);
+
+        let mut denoised = image.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let index = x + width * y;
+                let center_albedo = albedos[index];
+                let center_normal = normals[index];
+                let center_luminance = tone_mapping::luminance(colors[index]);
+                let standard_error = f32::sqrt(image.variance((x, y)) /
+                                               image.sample_count((x, y)) as f32);
+                let tolerance = f32::max(self.sigma_luminance * standard_error, 1e-4);
+
+                let mut sum = Rgb::new(0.0, 0.0, 0.0);
+                let mut weight_sum = 0.0;
+                for dy in -radius..radius + 1 {
+                    let qy = y as isize + dy;
+                    if qy < 0 || qy >= height as isize {
+                        continue;
+                    }
+                    for dx in -radius..radius + 1 {
+                        let qx = x as isize + dx;
+                        if qx < 0 || qx >= width as isize {
+                            continue;
+                        }
+                        let q = qx as usize + width * qy as usize;
+
+                        let mut exponent =
+                            albedo_factor * squared_distance(center_albedo, albedos[q]) +
+                            normal_factor * squared_distance(center_normal, normals[q]);
+                        if tolerance.is_finite() {
+                            let luminance = tone_mapping::luminance(colors[q]);
+                            exponent -= f32::abs(luminance - center_luminance) / tolerance;
+                        }
+
+                        let weight = spatial_weights[(dx + radius) as usize] *
+                                     spatial_weights[(dy + radius) as usize] *
+                                     f32::exp(exponent);
+                        sum = sum + colors[q] * weight;
+                        weight_sum += weight;
+                    }
+                }
+
+                // The pixel itself always has a weight of 1
+                denoised[(x, y)] = sum / weight_sum;
+            }
+        }
+
+        denoised
+    }
+}
@@ -2,47 +2,252 @@ use Vec3;
 use ray::Ray;
 use nalgebra as na;
 use std::f32;
-use rand;
-use rand::Rng;
+use std::f32::consts::PI;
+use sampler::Sampler;
+
+/// The shape of the lens opening, which shows up in out of focus
+/// highlights.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aperture {
+    Circular,
+    /// A regular polygon formed by the blades of the diaphragm. The rotation
+    /// is in radians.
+    Polygonal { blades: usize, rotation: f32 },
+}
+
+impl Aperture {
+    /// Maps uniformly distributed values in [0, 1) to a uniformly
+    /// distributed point on the aperture with a radius of 1.
+    pub fn sample(&self, u1: f32, u2: f32) -> (f32, f32) {
+        match *self {
+            Aperture::Polygonal { blades, rotation } if blades >= 3 => {
+                // Pick one of the triangles between the center and the
+                // edges of the polygon and reuse the rest of u1 within it
+                let u1 = u1 * blades as f32;
+                let blade = f32::min(u1.floor(), (blades - 1) as f32);
+                let u1 = u1 - blade;
+
+                let angle = 2.0 * PI / blades as f32;
+                let a = rotation + blade * angle;
+                let b = a + angle;
+
+                let s = f32::sqrt(u1);
+                let (wa, wb) = (s * (1.0 - u2), s * u2);
+                (wa * f32::cos(a) + wb * f32::cos(b), wa * f32::sin(a) + wb * f32::sin(b))
+            }
+            _ => {
+                let r = f32::sqrt(u1);
+                let phi = 2.0 * PI * u2;
+                (r * f32::cos(phi), r * f32::sin(phi))
+            }
+        }
+    }
+}
+
+/// How the directions around the camera get mapped onto the image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    /// The vertical field of view covers the height of the image.
+    Perspective,
+    /// Parallel rays along the viewing direction through a view of the given
+    /// height in world units.
+    Orthographic { height: f32 },
+    /// A 360° by 180° panorama, best rendered at an aspect ratio of 2:1.
+    Equirectangular,
+    /// An equidistant fisheye, where the distance to the center of the image
+    /// is proportional to the angle to the viewing direction. The field of
+    /// view covers the height of the image and everything outside of the
+    /// image circle stays black.
+    Fisheye,
+}
 
 pub struct Camera {
     pub dimensions: (usize, usize),
     pub position: Vec3,
+    /// In radians.
     pub field_of_view: f32,
+    pub projection: Projection,
+    /// The radius of the lens, which only the perspective projection has.
+    /// With a radius of 0 the camera is a pinhole camera and everything is
+    /// in focus.
+    pub aperture_radius: f32,
+    /// The distance along the viewing direction everything is in focus at.
+    pub focal_distance: f32,
+    pub aperture: Aperture,
+    yaw: f32,
+    pitch: f32,
+    roll: f32,
+    right: Vec3,
+    up: Vec3,
+    forward: Vec3,
 }
 
 impl Camera {
+    /// The camera starts out looking along +Z with +Y being up.
     pub fn new(dimensions: (usize, usize), position: Vec3, field_of_view: f32) -> Self {
         Camera {
             dimensions: dimensions,
             position: position,
             field_of_view: field_of_view,
+            projection: Projection::Perspective,
+            aperture_radius: 0.0,
+            focal_distance: 1.0,
+            aperture: Aperture::Circular,
+            yaw: 0.0,
+            pitch: 0.0,
+            roll: 0.0,
+            right: Vec3::new(1.0, 0.0, 0.0),
+            up: Vec3::new(0.0, 1.0, 0.0),
+            forward: Vec3::new(0.0, 0.0, 1.0),
         }
     }
 
-    pub fn get_ray_for_coordinate(&self, coord: (usize, usize)) -> Ray {
+    /// The basis of the camera without any roll.
+    fn unrolled_basis(yaw: f32, pitch: f32) -> (Vec3, Vec3, Vec3) {
+        let forward = Vec3::new(f32::sin(yaw) * f32::cos(pitch),
+                                f32::sin(pitch),
+                                f32::cos(yaw) * f32::cos(pitch));
+        let right = Vec3::new(f32::cos(yaw), 0.0, -f32::sin(yaw));
+        let up = na::cross(&forward, &right);
+        (right, up, forward)
+    }
+
+    fn update_basis(&mut self) {
+        let (right, up, forward) = Self::unrolled_basis(self.yaw, self.pitch);
+        let (sin_roll, cos_roll) = (f32::sin(self.roll), f32::cos(self.roll));
+        self.right = right * cos_roll + up * sin_roll;
+        self.up = up * cos_roll - right * sin_roll;
+        self.forward = forward;
+    }
+
+    /// Turns the camera towards `target`, keeping `up` as upright as
+    /// possible.
+    pub fn look_at(&mut self, target: Vec3, up: Vec3) {
+        let forward = na::normalize(&(target - self.position));
+        self.yaw = f32::atan2(forward.x, forward.z);
+        self.pitch = f32::asin(f32::max(-1.0, f32::min(1.0, forward.y)));
+
+        let (unrolled_right, unrolled_up, forward) = Self::unrolled_basis(self.yaw, self.pitch);
+        let up = up - forward * na::dot(&up, &forward);
+        self.roll = f32::atan2(-na::dot(&up, &unrolled_right), na::dot(&up, &unrolled_up));
+
+        self.update_basis();
+    }
+
+    /// The angle in radians the camera is turned to the right by, around
+    /// the y axis.
+    pub fn yaw(&self) -> f32 {
+        self.yaw
+    }
+
+    pub fn set_yaw(&mut self, yaw: f32) {
+        self.yaw = yaw;
+        self.update_basis();
+    }
+
+    /// The angle in radians the camera looks up by.
+    pub fn pitch(&self) -> f32 {
+        self.pitch
+    }
+
+    pub fn set_pitch(&mut self, pitch: f32) {
+        self.pitch = pitch;
+        self.update_basis();
+    }
+
+    /// The angle in radians the camera is tilted by around the direction it
+    /// looks in, counterclockwise from behind the camera.
+    pub fn roll(&self) -> f32 {
+        self.roll
+    }
+
+    pub fn set_roll(&mut self, roll: f32) {
+        self.roll = roll;
+        self.update_basis();
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        self.forward
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.right
+    }
+
+    pub fn up(&self) -> Vec3 {
+        self.up
+    }
+
+    fn to_world(&self, local: Vec3) -> Vec3 {
+        self.right * local.x + self.up * local.y + self.forward * local.z
+    }
+
+    /// Returns `None` for pixels the projection doesn't cover.
+    pub fn get_ray_for_coordinate(&self,
+                                  coord: (usize, usize),
+                                  sampler: &mut Sampler)
+                                  -> Option<Ray> {
         let (width, height) = self.dimensions;
         let (width, height) = (width as f32, height as f32);
         let (x, y) = coord;
         let (x, y) = (x as f32, y as f32);
 
-        let mut rng = rand::thread_rng();
-        let x = x + rng.gen_range(-0.5, 0.5);
-        let y = y + rng.gen_range(-0.5, 0.5);
-
-        let origin = self.position;
-
-        let d = 1.0 / f32::tan(self.field_of_view / 2.0);
+        let (jitter_x, jitter_y) = sampler.next_2d();
+        let x = x + jitter_x - 0.5;
+        let y = y + jitter_y - 0.5;
 
         let x = 2.0 * x / width - 1.0;
         let y = -2.0 * y / height + 1.0;
+        let aspect_ratio = width / height;
+
+        match self.projection {
+            Projection::Perspective => {}
+            Projection::Orthographic { height } => {
+                let offset = self.right * (0.5 * height * aspect_ratio * x) +
+                             self.up * (0.5 * height * y);
+                return Some(Ray::new(self.position + offset, self.forward));
+            }
+            Projection::Equirectangular => {
+                let longitude = PI * x;
+                let latitude = 0.5 * PI * y;
+                let local = Vec3::new(f32::cos(latitude) * f32::sin(longitude),
+                                      f32::sin(latitude),
+                                      f32::cos(latitude) * f32::cos(longitude));
+                return Some(Ray::new(self.position, self.to_world(local)));
+            }
+            Projection::Fisheye => {
+                let x = aspect_ratio * x;
+                let radius = f32::sqrt(x * x + y * y);
+                if radius > 1.0 {
+                    return None;
+                }
+                let theta = 0.5 * self.field_of_view * radius;
+                let phi = f32::atan2(y, x);
+                let local = Vec3::new(f32::sin(theta) * f32::cos(phi),
+                                      f32::sin(theta) * f32::sin(phi),
+                                      f32::cos(theta));
+                return Some(Ray::new(self.position, self.to_world(local)));
+            }
+        }
+
+        let d = 1.0 / f32::tan(self.field_of_view / 2.0);
 
-        let x = (width / height) * x / d;
+        let x = aspect_ratio * x / d;
         let y = y / d;
 
-        let v = Vec3::new(x, y, 1.0);
-        let direction = na::normalize(&v);
+        let v = self.to_world(Vec3::new(x, y, 1.0));
+
+        if self.aperture_radius <= 0.0 {
+            return Some(Ray::new(self.position, na::normalize(&v)));
+        }
+
+        // All the rays through the lens converge on the focal plane
+        let focus = self.position + v * self.focal_distance;
+        let (u1, u2) = sampler.next_2d();
+        let (lens_x, lens_y) = self.aperture.sample(u1, u2);
+        let origin = self.position +
+                     (self.right * lens_x + self.up * lens_y) * self.aperture_radius;
 
-        Ray::new(origin, direction)
+        Some(Ray::new(origin, na::normalize(&(focus - origin))))
     }
 }
@@ -1,93 +1,432 @@
 use prelude::*;
 use {Ray, Collision};
+use aabb::Aabb;
+use bvh::Bvh;
+use transform::Transform;
 use nalgebra as na;
+use std::collections::HashMap;
 
 pub struct Triangle {
     a: Vec3,
     b: Vec3,
     c: Vec3,
+    /// Get interpolated across the triangle for smooth shading. Without them
+    /// the triangle is flat.
+    normals: Option<[Vec3; 3]>,
+    uvs: Option<[(f32, f32); 3]>,
 }
 
+/// The attributes of the vertices of a mesh. Every attribute that's there
+/// has an entry for every position.
+#[derive(Clone, Debug, Default)]
+pub struct Vertices {
+    pub positions: Vec<Vec3>,
+    /// Get interpolated across the triangles for smooth shading. Without
+    /// them the triangles are flat.
+    pub normals: Option<Vec<Vec3>>,
+    pub uvs: Option<Vec<(f32, f32)>>,
+    /// Point in the direction the u coordinate increases in.
+    pub tangents: Option<Vec<Vec3>>,
+}
+
+/// Triangles that share their vertices. Every triangle is a triple of
+/// indices into the vertices, which are in object space.
 pub struct Mesh<BrdfType: Brdf + 'static> {
-    triangles: Vec<Triangle>,
-    position: Vec3,
+    vertices: Vertices,
+    indices: Vec<[usize; 3]>,
+    bvh: Bvh,
+    transform: Transform,
     brdf: BrdfType,
 }
 
+/// The Möller–Trumbore intersection. Returns the distance along the ray and
+/// the barycentric u and v, which are the weights of b and c.
+fn intersect(a: Vec3, b: Vec3, c: Vec3, ray: &Ray) -> Option<(f32, f32, f32)> {
+    let e1 = b - a;
+    let e2 = c - a;
+
+    let h = na::cross(&ray.direction, &e2);
+    let det = na::dot(&e1, &h);
+
+    if det > -0.00001 && det < 0.00001 {
+        return None;
+    }
+
+    let f = 1.0 / det;
+
+    let s = ray.origin - a;
+    let u = f * na::dot(&s, &h);
+
+    if u < 0.0 || u > 1.0 {
+        return None;
+    }
+
+    let q = na::cross(&s, &e1);
+    let v = f * na::dot(&ray.direction, &q);
+
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = f * na::dot(&e2, &q);
+
+    if t > 0.00001 {
+        Some((t, u, v))
+    } else {
+        None
+    }
+}
+
+/// Triangles without any area have no normal of their own, so they get an
+/// arbitrary one instead of NaNs.
+fn face_normal(a: Vec3, b: Vec3, c: Vec3) -> Vec3 {
+    let normal = na::cross(&(b - a), &(c - a));
+    if na::dot(&normal, &normal) > 0.0 {
+        na::normalize(&normal)
+    } else {
+        Vec3::new(0.0, 1.0, 0.0)
+    }
+}
+
+/// Interpolates a direction, falling back to `fallback` where the
+/// directions cancel out.
+fn interpolate_direction(directions: [Vec3; 3],
+                         barycentrics: (f32, f32),
+                         fallback: Vec3)
+                         -> Vec3 {
+    let (u, v) = barycentrics;
+    let direction = directions[0] * (1.0 - u - v) + directions[1] * u + directions[2] * v;
+    if na::dot(&direction, &direction) > 0.0 {
+        na::normalize(&direction)
+    } else {
+        fallback
+    }
+}
+
+fn interpolate_uv(uvs: [(f32, f32); 3], barycentrics: (f32, f32)) -> (f32, f32) {
+    let (u, v) = barycentrics;
+    let w = 1.0 - u - v;
+    (uvs[0].0 * w + uvs[1].0 * u + uvs[2].0 * v,
+     uvs[0].1 * w + uvs[1].1 * u + uvs[2].1 * v)
+}
+
 impl Triangle {
     pub fn new(a: Vec3, b: Vec3, c: Vec3) -> Self {
-        Triangle { a: a, b: b, c: c }
+        Triangle {
+            a: a,
+            b: b,
+            c: c,
+            normals: None,
+            uvs: None,
+        }
     }
 
-    pub fn collides_with<'a, BrdfType: Brdf + 'static>(&'a self,
-                                                       ray: &Ray,
-                                                       brdf: &'a BrdfType)
-                                                       -> Option<Collision<BrdfType>> {
-        let e1 = self.b - self.a;
-        let e2 = self.c - self.a;
+    /// Sets the normals at the vertices, which don't have to be normalized.
+    pub fn with_normals(mut self, normals: [Vec3; 3]) -> Self {
+        self.normals = Some(normals);
+        self
+    }
 
-        let h = na::cross(&ray.direction, &e2);
-        let a = na::dot(&e1, &h);
+    pub fn with_uvs(mut self, uvs: [(f32, f32); 3]) -> Self {
+        self.uvs = Some(uvs);
+        self
+    }
 
-        if a > -0.00001 && a < 0.00001 {
-            return None;
-        }
+    pub fn positions(&self) -> [Vec3; 3] {
+        [self.a, self.b, self.c]
+    }
 
-        let f = 1.0 / a;
+    pub fn normals(&self) -> Option<&[Vec3; 3]> {
+        self.normals.as_ref()
+    }
 
-        let s = ray.origin - self.a;
-        let u = f * na::dot(&s, &h);
+    pub fn uvs(&self) -> Option<&[(f32, f32); 3]> {
+        self.uvs.as_ref()
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        Aabb::new(self.a, self.a).grow(self.b).grow(self.c)
+    }
+}
+
+/// Bit patterns of all the attributes of a vertex, so that identical
+/// vertices can be merged.
+type VertexKey = ([u32; 3], Option<[u32; 3]>, Option<[u32; 2]>);
 
-        if u < 0.0 || u > 1.0 {
-            return None;
+/// Treats -0 and 0 as the same value.
+fn float_bits(x: f32) -> u32 {
+    if x == 0.0 {
+        0
+    } else {
+        x.to_bits()
+    }
+}
+
+fn vector_bits(v: Vec3) -> [u32; 3] {
+    [float_bits(v.x), float_bits(v.y), float_bits(v.z)]
+}
+
+impl Vertices {
+    pub fn new(positions: Vec<Vec3>) -> Self {
+        Vertices {
+            positions: positions,
+            normals: None,
+            uvs: None,
+            tangents: None,
         }
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Merges the vertices of the triangles that are exactly the same. The
+    /// vertices of flat triangles get the face normal if other triangles
+    /// have normals, and missing texture coordinates become (0, 0).
+    pub fn from_triangles(triangles: &[Triangle]) -> (Self, Vec<[usize; 3]>) {
+        let has_normals = triangles.iter().any(|triangle| triangle.normals.is_some());
+        let has_uvs = triangles.iter().any(|triangle| triangle.uvs.is_some());
+
+        let mut vertices = Vertices::new(Vec::new());
+        let mut normals = Vec::new();
+        let mut uvs = Vec::new();
+        let mut indices = Vec::with_capacity(triangles.len());
+        let mut merged: HashMap<VertexKey, usize> = HashMap::new();
+
+        for triangle in triangles {
+            let positions = triangle.positions();
+            let face_normal = face_normal(triangle.a, triangle.b, triangle.c);
+            let mut face = [0; 3];
 
-        let q = na::cross(&s, &e1);
-        let v = f * na::dot(&ray.direction, &q);
+            for corner in 0..3 {
+                let position = positions[corner];
+                let normal = triangle.normals.map_or(face_normal, |normals| normals[corner]);
+                let uv = triangle.uvs.map_or((0.0, 0.0), |uvs| uvs[corner]);
 
-        if v < 0.0 || u + v > 1.0 {
-            return None;
+                let key = (vector_bits(position),
+                           if has_normals {
+                               Some(vector_bits(normal))
+                           } else {
+                               None
+                           },
+                           if has_uvs {
+                               Some([float_bits(uv.0), float_bits(uv.1)])
+                           } else {
+                               None
+                           });
+                let next_index = vertices.positions.len();
+                face[corner] = *merged.entry(key).or_insert_with(|| {
+                    vertices.positions.push(position);
+                    normals.push(normal);
+                    uvs.push(uv);
+                    next_index
+                });
+            }
+            indices.push(face);
         }
 
-        let t = f * na::dot(&e2, &q);
+        if has_normals {
+            vertices.normals = Some(normals);
+        }
+        if has_uvs {
+            vertices.uvs = Some(uvs);
+        }
+        (vertices, indices)
+    }
 
-        if t > 0.00001 {
-            let hit_position = ray.direction * t + ray.origin;
-            let hit_normal = na::cross(&e1, &e2);
-            Some(Collision::new(hit_position, hit_normal, brdf))
-        } else {
-            None
+    /// Derives the tangents from the positions and texture coordinates, by
+    /// averaging them over the triangles that share a vertex.
+    pub fn compute_tangents(&mut self, indices: &[[usize; 3]]) {
+        let uvs = match self.uvs {
+            Some(ref uvs) => uvs,
+            None => return,
+        };
+        let mut tangents = vec![Vec3::new(0.0, 0.0, 0.0); self.positions.len()];
+
+        for face in indices {
+            let [a, b, c] = *face;
+            let e1 = self.positions[b] - self.positions[a];
+            let e2 = self.positions[c] - self.positions[a];
+            let (du1, dv1) = (uvs[b].0 - uvs[a].0, uvs[b].1 - uvs[a].1);
+            let (du2, dv2) = (uvs[c].0 - uvs[a].0, uvs[c].1 - uvs[a].1);
+
+            let det = du1 * dv2 - du2 * dv1;
+            if det == 0.0 {
+                continue;
+            }
+            let tangent = (e1 * dv2 - e2 * dv1) / det;
+            for &index in face {
+                tangents[index] = tangents[index] + tangent;
+            }
+        }
+
+        for tangent in &mut tangents {
+            if na::dot(tangent, tangent) > 0.0 {
+                *tangent = na::normalize(tangent);
+            }
         }
+        self.tangents = Some(tangents);
+    }
+
+    fn check(&self, indices: &[[usize; 3]]) {
+        let count = self.positions.len();
+        assert!(self.normals.as_ref().map_or(true, |normals| normals.len() == count),
+                "Expected a normal for every vertex");
+        assert!(self.uvs.as_ref().map_or(true, |uvs| uvs.len() == count),
+                "Expected texture coordinates for every vertex");
+        assert!(self.tangents.as_ref().map_or(true, |tangents| tangents.len() == count),
+                "Expected a tangent for every vertex");
+        assert!(indices.iter().all(|face| face.iter().all(|&index| index < count)),
+                "Expected the indices to refer to the vertices");
     }
 }
 
 impl<BrdfType: Brdf + 'static> Mesh<BrdfType> {
+    /// Builds an indexed mesh out of separate triangles, merging the vertices
+    /// they share.
     pub fn new(triangles: Vec<Triangle>, brdf: BrdfType) -> Self {
+        let (vertices, indices) = Vertices::from_triangles(&triangles);
+        Self::from_arrays(vertices, indices, brdf)
+    }
+
+    /// Panics if an attribute is missing for some of the vertices or an
+    /// index is out of bounds.
+    pub fn from_arrays(vertices: Vertices, indices: Vec<[usize; 3]>, brdf: BrdfType) -> Self {
+        vertices.check(&indices);
+        let bvh = Self::build_bvh(&vertices, &indices);
         Mesh {
-            triangles: triangles,
-            position: Vec3::new(0.0, 0.0, 0.0),
+            vertices: vertices,
+            indices: indices,
+            bvh: bvh,
+            transform: Transform::identity(),
             brdf: brdf,
         }
     }
+
+    fn build_bvh(vertices: &Vertices, indices: &[[usize; 3]]) -> Bvh {
+        let positions = &vertices.positions;
+        let bounds = indices.iter()
+                            .map(|&[a, b, c]| {
+                                Aabb::new(positions[a], positions[a])
+                                    .grow(positions[b])
+                                    .grow(positions[c])
+                            })
+                            .collect::<Vec<_>>();
+        Bvh::new(&bounds)
+    }
+
+    pub fn vertices(&self) -> &Vertices {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &[[usize; 3]] {
+        &self.indices
+    }
+
+    /// Gives mutable access to the vertices. The BVH doesn't track the
+    /// changes, so call `rebuild_bvh` once you're done modifying them.
+    pub fn vertices_mut(&mut self) -> &mut Vertices {
+        &mut self.vertices
+    }
+
+    pub fn set_arrays(&mut self, vertices: Vertices, indices: Vec<[usize; 3]>) {
+        vertices.check(&indices);
+        self.vertices = vertices;
+        self.indices = indices;
+        self.rebuild_bvh();
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// Copies a triangle out of the shared vertices.
+    pub fn triangle(&self, index: usize) -> Triangle {
+        let [a, b, c] = self.indices[index];
+        let vertices = &self.vertices;
+        let mut triangle = Triangle::new(vertices.positions[a],
+                                         vertices.positions[b],
+                                         vertices.positions[c]);
+        if let Some(ref normals) = vertices.normals {
+            triangle = triangle.with_normals([normals[a], normals[b], normals[c]]);
+        }
+        if let Some(ref uvs) = vertices.uvs {
+            triangle = triangle.with_uvs([uvs[a], uvs[b], uvs[c]]);
+        }
+        triangle
+    }
+
+    pub fn triangles(&self) -> Vec<Triangle> {
+        (0..self.indices.len()).map(|index| self.triangle(index)).collect()
+    }
+
+    pub fn set_triangles(&mut self, triangles: Vec<Triangle>) {
+        let (vertices, indices) = Vertices::from_triangles(&triangles);
+        self.set_arrays(vertices, indices);
+    }
+
+    pub fn rebuild_bvh(&mut self) {
+        self.bvh = Self::build_bvh(&self.vertices, &self.indices);
+    }
+
+    fn collides_with_face(&self, index: usize, ray: &Ray) -> Option<Collision> {
+        let [a, b, c] = self.indices[index];
+        let vertices = &self.vertices;
+        let positions = &vertices.positions;
+
+        intersect(positions[a], positions[b], positions[c], ray).map(|(t, u, v)| {
+            let barycentrics = (u, v);
+            let geometric_normal = face_normal(positions[a], positions[b], positions[c]);
+            let normal = match vertices.normals {
+                Some(ref normals) => {
+                    interpolate_direction([normals[a], normals[b], normals[c]],
+                                          barycentrics,
+                                          geometric_normal)
+                }
+                None => geometric_normal,
+            };
+
+            let hit_position = ray.direction * t + ray.origin;
+            let mut collision = Collision::new(t, hit_position, normal, &self.brdf);
+            collision.barycentrics = Some(barycentrics);
+            collision.uv = vertices.uvs.as_ref().map(|uvs| {
+                interpolate_uv([uvs[a], uvs[b], uvs[c]], barycentrics)
+            });
+            collision.tangent = vertices.tangents.as_ref().map(|tangents| {
+                interpolate_direction([tangents[a], tangents[b], tangents[c]],
+                                      barycentrics,
+                                      tangents[a])
+            });
+            collision
+        })
+    }
 }
 
 impl<BrdfType: Brdf + 'static> Entity for Mesh<BrdfType> {
-    type BrdfType = BrdfType;
+    fn collides_with(&self, ray: &Ray) -> Option<Collision> {
+        let local = self.transform.ray_to_object(ray);
+        self.bvh
+            .closest_hit(&local, |index| self.collides_with_face(index, &local))
+            .map(|collision| self.transform.collision_to_world(ray, collision))
+    }
 
-    fn collides_with(&self, ray: &Ray) -> Option<Collision<Self::BrdfType>> {
-        for triangle in &self.triangles {
-            if let Some(collision) = triangle.collides_with(ray, &self.brdf) {
-                return Some(collision);
-            }
-        }
-        None
+    fn bounds(&self) -> Aabb {
+        self.transform.transform_aabb(&self.bvh.bounds())
+    }
+
+    fn transform(&self) -> &Transform {
+        &self.transform
     }
 
-    fn position(&self) -> Vec3 {
-        self.position
+    fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
     }
 
-    fn set_position(&mut self, p: Vec3) {
-        self.position = p;
+    fn brdf(&self) -> &Brdf {
+        &self.brdf
     }
 }
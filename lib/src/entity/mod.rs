@@ -1,12 +1,28 @@
 use prelude::*;
 use ray::Ray;
 use collision::Collision;
+use aabb::Aabb;
+use transform::Transform;
 
 pub trait Entity {
-    type BrdfType: Brdf;
-    fn collides_with(&self, ray: &Ray) -> Option<Collision<Self::BrdfType>>;
-    fn position(&self) -> Vec3;
-    fn set_position(&mut self, p: Vec3);
+    fn collides_with(&self, ray: &Ray) -> Option<Collision>;
+    /// In world space.
+    fn bounds(&self) -> Aabb;
+    /// Maps the object space the entity is defined in to world space.
+    fn transform(&self) -> &Transform;
+    fn set_transform(&mut self, transform: Transform);
+    fn brdf(&self) -> &Brdf;
+
+    fn position(&self) -> Vec3 {
+        self.transform().translation()
+    }
+
+    /// Moves the entity without rotating or scaling it.
+    fn set_position(&mut self, p: Vec3) {
+        let mut transform = *self.transform();
+        transform.set_translation(p);
+        self.set_transform(transform);
+    }
 }
 
 pub mod sphere;
@@ -14,5 +30,5 @@ pub mod camera;
 pub mod mesh;
 
 pub use self::sphere::Sphere;
-pub use self::camera::Camera;
-pub use self::mesh::{Triangle, Mesh};
+pub use self::camera::{Camera, Aperture, Projection};
+pub use self::mesh::{Triangle, Mesh, Vertices};
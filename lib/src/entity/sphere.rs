@@ -4,58 +4,73 @@ use brdf::Brdf;
 use entity::Entity;
 use ray::Ray;
 use collision::Collision;
+use aabb::Aabb;
+use transform::Transform;
 use std::f32;
 
+/// A solid sphere around the origin of its object space. Non-uniform
+/// scaling turns it into an ellipsoid.
 pub struct Sphere<BrdfType: Brdf + 'static> {
-    center: Vec3,
     radius: f32,
+    transform: Transform,
     brdf: BrdfType,
 }
 
 impl<BrdfType: Brdf + 'static> Sphere<BrdfType> {
     pub fn new(center: Vec3, radius: f32, brdf: BrdfType) -> Self {
         Sphere {
-            center: center,
             radius: radius,
+            transform: Transform::from_translation(center),
             brdf: brdf,
         }
     }
 }
 
 impl<BrdfType: Brdf + 'static> Entity for Sphere<BrdfType> {
-    type BrdfType = BrdfType;
-
-    fn collides_with(&self, ray: &Ray) -> Option<Collision<Self::BrdfType>> {
-        let m = ray.origin - self.center;
-        let b = na::dot(&m, &ray.direction);
+    fn collides_with(&self, ray: &Ray) -> Option<Collision> {
+        // The direction isn't normalized in object space
+        let local = self.transform.ray_to_object(ray);
+        let m = local.origin;
+        let a = na::dot(&local.direction, &local.direction);
+        let b = na::dot(&m, &local.direction);
         let c = na::dot(&m, &m) - self.radius * self.radius;
 
         if c > 0.0 && b > 0.0 {
             return None;
         }
 
-        let discr = b * b - c;
+        let discr = b * b - a * c;
 
         if discr < 0.0 {
             return None;
         }
 
-        let mut t = -b - f32::sqrt(discr);
+        let mut t = (-b - f32::sqrt(discr)) / a;
 
         if t < 0.0 {
             t = 0.0;
         }
 
-        let hit_position = ray.direction * t + ray.origin;
-        let hit_normal = na::normalize(&(hit_position - self.center));
-        Some(Collision::new(hit_position, hit_normal, &self.brdf))
+        // Around the origin the position doubles as the normal
+        let hit_position = local.direction * t + m;
+        let collision = Collision::new(t, hit_position, hit_position, &self.brdf);
+        Some(self.transform.collision_to_world(ray, collision))
+    }
+
+    fn bounds(&self) -> Aabb {
+        let radius = Vec3::new(self.radius, self.radius, self.radius);
+        self.transform.transform_aabb(&Aabb::new(radius * -1.0, radius))
+    }
+
+    fn transform(&self) -> &Transform {
+        &self.transform
     }
 
-    fn position(&self) -> Vec3 {
-        self.center
+    fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
     }
 
-    fn set_position(&mut self, p: Vec3) {
-        self.center = p;
+    fn brdf(&self) -> &Brdf {
+        &self.brdf
     }
 }
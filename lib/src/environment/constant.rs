@@ -0,0 +1,18 @@
+use prelude::*;
+use super::Environment;
+
+pub struct Constant {
+    radiance: Rgb,
+}
+
+impl Constant {
+    pub fn new(radiance: Rgb) -> Self {
+        Constant { radiance: radiance }
+    }
+}
+
+impl Environment for Constant {
+    fn radiance(&self, _: Vec3) -> Rgb {
+        self.radiance
+    }
+}
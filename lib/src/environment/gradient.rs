@@ -0,0 +1,24 @@
+use prelude::*;
+use super::Environment;
+
+/// Blends from `bottom` straight down to `top` straight up.
+pub struct Gradient {
+    bottom: Rgb,
+    top: Rgb,
+}
+
+impl Gradient {
+    pub fn new(bottom: Rgb, top: Rgb) -> Self {
+        Gradient {
+            bottom: bottom,
+            top: top,
+        }
+    }
+}
+
+impl Environment for Gradient {
+    fn radiance(&self, direction: Vec3) -> Rgb {
+        let t = 0.5 * (direction.y + 1.0);
+        self.bottom * (1.0 - t) + self.top * t
+    }
+}
@@ -0,0 +1,192 @@
+use prelude::*;
+use super::Environment;
+use light::LightSample;
+use hdr;
+use palette::{FromColor, Luma};
+use sampler::Sampler;
+use std::f32;
+use std::f32::consts::PI;
+use std::io;
+use std::path::Path;
+
+/// A piecewise constant distribution over [0, 1).
+struct Distribution {
+    cdf: Vec<f32>,
+    integral: f32,
+}
+
+impl Distribution {
+    fn new(values: &[f32]) -> Self {
+        let count = values.len();
+        let mut cdf = Vec::with_capacity(count + 1);
+        let mut sum = 0.0;
+        cdf.push(0.0);
+        for &value in values {
+            sum += value;
+            cdf.push(sum);
+        }
+
+        if sum > 0.0 {
+            for value in &mut cdf {
+                *value /= sum;
+            }
+        } else {
+            for (i, value) in cdf.iter_mut().enumerate() {
+                *value = i as f32 / count as f32;
+            }
+        }
+
+        Distribution {
+            cdf: cdf,
+            integral: sum / count as f32,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.cdf.len() - 1
+    }
+
+    /// Returns the sampled position along with the index of its segment.
+    fn sample(&self, u: f32) -> (f32, usize) {
+        // Find the last segment that starts at or before u
+        let (mut low, mut high) = (0, self.len() - 1);
+        while low < high {
+            let middle = (low + high + 1) / 2;
+            if self.cdf[middle] <= u {
+                low = middle;
+            } else {
+                high = middle - 1;
+            }
+        }
+
+        let width = self.cdf[low + 1] - self.cdf[low];
+        let offset = if width > 0.0 {
+            (u - self.cdf[low]) / width
+        } else {
+            0.0
+        };
+        ((low as f32 + offset) / self.len() as f32, low)
+    }
+
+    fn pdf(&self, index: usize) -> f32 {
+        (self.cdf[index + 1] - self.cdf[index]) * self.len() as f32
+    }
+}
+
+/// An environment stored as an equirectangular image, with the top row
+/// straight up and the center of the image towards +Z. The map gets
+/// importance sampled by the luminance of its pixels.
+pub struct EnvironmentMap {
+    dimensions: (usize, usize),
+    pixels: Vec<Rgb>,
+    intensity: f32,
+    /// Picks the row.
+    marginal: Distribution,
+    /// Picks the column within every row.
+    conditionals: Vec<Distribution>,
+}
+
+impl EnvironmentMap {
+    /// The pixels are in row major order and get multiplied by `intensity`.
+    /// Panics if the map is empty or the pixels don't match the dimensions.
+    pub fn new(dimensions: (usize, usize), pixels: Vec<Rgb>, intensity: f32) -> Self {
+        let (width, height) = dimensions;
+        assert!(width > 0 && height > 0, "Expected a non-empty environment map");
+        assert!(pixels.len() == width * height,
+                "Expected a pixel for every position in the environment map");
+
+        let conditionals = pixels.chunks(width)
+                                 .enumerate()
+                                 .map(|(y, row)| {
+                                     // Rows close to the poles cover less solid angle
+                                     let sin_theta = f32::sin(PI * (y as f32 + 0.5) /
+                                                              height as f32);
+                                     let values = row.iter()
+                                                     .map(|&pixel| {
+                                                         Luma::from_rgb(pixel).luma * sin_theta
+                                                     })
+                                                     .collect::<Vec<_>>();
+                                     Distribution::new(&values)
+                                 })
+                                 .collect::<Vec<_>>();
+
+        let marginal = Distribution::new(&conditionals.iter()
+                                                      .map(|row| row.integral)
+                                                      .collect::<Vec<_>>());
+
+        EnvironmentMap {
+            dimensions: dimensions,
+            pixels: pixels,
+            intensity: intensity,
+            marginal: marginal,
+            conditionals: conditionals,
+        }
+    }
+
+    /// Loads a Radiance HDR image.
+    pub fn open<P: AsRef<Path>>(path: P, intensity: f32) -> io::Result<Self> {
+        let (dimensions, pixels) = try!(hdr::open(path));
+        if dimensions.0 == 0 || dimensions.1 == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Empty environment map"));
+        }
+        Ok(EnvironmentMap::new(dimensions, pixels, intensity))
+    }
+
+    /// Maps a direction to its column and row along with the sine of its
+    /// angle to the y axis.
+    fn pixel_for(&self, direction: Vec3) -> (usize, usize, f32) {
+        let (width, height) = self.dimensions;
+        let u = 0.5 + f32::atan2(direction.x, direction.z) / (2.0 * PI);
+        let v = f32::acos(direction.y.clamp(-1.0, 1.0)) / PI;
+        let x = usize::min((u * width as f32) as usize, width - 1);
+        let y = usize::min((v * height as f32) as usize, height - 1);
+        let sin_theta = f32::sqrt(f32::max(0.0, 1.0 - direction.y * direction.y));
+        (x, y, sin_theta)
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> Rgb {
+        self.pixels[y * self.dimensions.0 + x] * self.intensity
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, direction: Vec3) -> Rgb {
+        let (x, y, _) = self.pixel_for(direction);
+        self.pixel(x, y)
+    }
+
+    fn sample(&self, sampler: &mut Sampler) -> Option<LightSample> {
+        let (u1, u2) = sampler.next_2d();
+        let (v, y) = self.marginal.sample(u1);
+        let (u, x) = self.conditionals[y].sample(u2);
+
+        let theta = PI * v;
+        let phi = 2.0 * PI * (u - 0.5);
+        let sin_theta = f32::sin(theta);
+        if sin_theta <= 0.0 {
+            return None;
+        }
+
+        // Converts the density over the image to one over solid angle
+        let pdf = self.marginal.pdf(y) * self.conditionals[y].pdf(x) /
+                  (2.0 * PI * PI * sin_theta);
+
+        Some(LightSample {
+            direction: Vec3::new(sin_theta * f32::sin(phi),
+                                 f32::cos(theta),
+                                 sin_theta * f32::cos(phi)),
+            distance: f32::INFINITY,
+            radiance: self.pixel(x, y),
+            pdf: pdf,
+            is_delta: false,
+        })
+    }
+
+    fn pdf(&self, direction: Vec3) -> f32 {
+        let (x, y, sin_theta) = self.pixel_for(direction);
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.marginal.pdf(y) * self.conditionals[y].pdf(x) / (2.0 * PI * PI * sin_theta)
+    }
+}
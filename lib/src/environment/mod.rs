@@ -0,0 +1,47 @@
+use prelude::*;
+use light::LightSample;
+use sampler::Sampler;
+
+/// The radiance arriving from infinitely far away, seen by every ray that
+/// doesn't hit anything.
+pub trait Environment {
+    fn radiance(&self, direction: Vec3) -> Rgb;
+
+    /// Picks a direction towards the environment for next event estimation.
+    /// Environments that aren't worth sampling explicitly only get found by
+    /// BRDF samples.
+    fn sample(&self, _sampler: &mut Sampler) -> Option<LightSample> {
+        None
+    }
+
+    /// The density `sample` picks the direction with.
+    fn pdf(&self, _direction: Vec3) -> f32 {
+        0.0
+    }
+}
+
+impl<T: Environment + ?Sized> Environment for Box<T> {
+    fn radiance(&self, direction: Vec3) -> Rgb {
+        (**self).radiance(direction)
+    }
+
+    fn sample(&self, sampler: &mut Sampler) -> Option<LightSample> {
+        (**self).sample(sampler)
+    }
+
+    fn pdf(&self, direction: Vec3) -> f32 {
+        (**self).pdf(direction)
+    }
+}
+
+pub mod constant;
+pub mod gradient;
+pub mod sky;
+pub mod sun;
+pub mod map;
+
+pub use self::constant::Constant;
+pub use self::gradient::Gradient;
+pub use self::sky::Sky;
+pub use self::sun::Sun;
+pub use self::map::EnvironmentMap;
@@ -0,0 +1,120 @@
+use prelude::*;
+use super::Environment;
+use nalgebra as na;
+use palette::{FromColor, Yxy};
+use std::f32;
+use std::f32::consts::PI;
+
+/// The coefficients of the Perez sky luminance distribution for one of the
+/// Yxy components.
+struct Perez {
+    a: f32,
+    b: f32,
+    c: f32,
+    d: f32,
+    e: f32,
+}
+
+impl Perez {
+    fn new(turbidity: f32, coefficients: [[f32; 2]; 5]) -> Self {
+        let coefficient = |i: usize| coefficients[i][0] * turbidity + coefficients[i][1];
+        Perez {
+            a: coefficient(0),
+            b: coefficient(1),
+            c: coefficient(2),
+            d: coefficient(3),
+            e: coefficient(4),
+        }
+    }
+
+    /// `cos_theta` is the cosine of the angle to the zenith and `gamma` the
+    /// angle to the sun.
+    fn evaluate(&self, cos_theta: f32, gamma: f32) -> f32 {
+        let cos_gamma = f32::cos(gamma);
+        (1.0 + self.a * f32::exp(self.b / cos_theta)) *
+        (1.0 + self.c * f32::exp(self.d * gamma) + self.e * cos_gamma * cos_gamma)
+    }
+}
+
+/// The analytic daylight model from "A Practical Analytic Model for
+/// Daylight" by Preetham et al. It doesn't contain the sun itself, which
+/// can be added with `Sun`.
+pub struct Sky {
+    sun_direction: Vec3,
+    perez: [Perez; 3],
+    /// The Yxy values at the zenith divided by the Perez function there.
+    zenith: [f32; 3],
+    scale: f32,
+}
+
+impl Sky {
+    /// `sun_direction` points towards the sun. `turbidity` describes the
+    /// haziness of the atmosphere, from about 2 for a clear sky to 10 for
+    /// a hazy one. The resulting radiance in kcd/m² gets multiplied by
+    /// `scale`.
+    pub fn new(sun_direction: Vec3, turbidity: f32, scale: f32) -> Self {
+        let sun_direction = na::normalize(&sun_direction);
+        let t = turbidity;
+
+        // The model breaks down for a sun below the horizon
+        let theta_s = f32::min(f32::acos(sun_direction.y.clamp(-1.0, 1.0)), 0.5 * PI - 0.01);
+        let theta_s2 = theta_s * theta_s;
+        let theta_s3 = theta_s2 * theta_s;
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * f32::tan(chi) - 0.2155 * t + 2.4192;
+
+        let zenith_x = t * t * (0.00166 * theta_s3 - 0.00375 * theta_s2 + 0.00209 * theta_s) +
+                       t * (-0.02903 * theta_s3 + 0.06377 * theta_s2 - 0.03202 * theta_s +
+                            0.00394) +
+                       (0.11693 * theta_s3 - 0.21196 * theta_s2 + 0.06052 * theta_s + 0.25886);
+        let zenith_y = t * t * (0.00275 * theta_s3 - 0.00610 * theta_s2 + 0.00317 * theta_s) +
+                       t * (-0.04214 * theta_s3 + 0.08970 * theta_s2 - 0.04153 * theta_s +
+                            0.00516) +
+                       (0.15346 * theta_s3 - 0.26756 * theta_s2 + 0.06670 * theta_s + 0.26688);
+
+        let perez = [Perez::new(t,
+                                [[0.1787, -1.4630],
+                                 [-0.3554, 0.4275],
+                                 [-0.0227, 5.3251],
+                                 [0.1206, -2.5771],
+                                 [-0.0670, 0.3703]]),
+                     Perez::new(t,
+                                [[-0.0193, -0.2592],
+                                 [-0.0665, 0.0008],
+                                 [-0.0004, 0.2125],
+                                 [-0.0641, -0.8989],
+                                 [-0.0033, 0.0452]]),
+                     Perez::new(t,
+                                [[-0.0167, -0.2608],
+                                 [-0.0950, 0.0092],
+                                 [-0.0079, 0.2102],
+                                 [-0.0441, -1.6537],
+                                 [-0.0109, 0.0529]])];
+
+        let zenith = [zenith_luminance / perez[0].evaluate(1.0, theta_s),
+                      zenith_x / perez[1].evaluate(1.0, theta_s),
+                      zenith_y / perez[2].evaluate(1.0, theta_s)];
+
+        Sky {
+            sun_direction: sun_direction,
+            perez: perez,
+            zenith: zenith,
+            scale: scale,
+        }
+    }
+}
+
+impl Environment for Sky {
+    fn radiance(&self, direction: Vec3) -> Rgb {
+        // Below the horizon the sky keeps looking like it does at the horizon
+        let cos_theta = f32::max(direction.y, 0.01);
+        let gamma = f32::acos(na::dot(&direction, &self.sun_direction).clamp(-1.0, 1.0));
+
+        let luminance = self.zenith[0] * self.perez[0].evaluate(cos_theta, gamma);
+        let x = self.zenith[1] * self.perez[1].evaluate(cos_theta, gamma);
+        let y = self.zenith[2] * self.perez[2].evaluate(cos_theta, gamma);
+
+        Rgb::from_yxy(Yxy::new(x, y, luminance * self.scale))
+    }
+}
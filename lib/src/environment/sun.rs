@@ -0,0 +1,68 @@
+use prelude::*;
+use super::Environment;
+use light::LightSample;
+use brdf::sampling;
+use nalgebra as na;
+use sampler::Sampler;
+use std::f32;
+use std::f32::consts::PI;
+
+/// Puts a sun disc of constant radiance in front of another environment.
+/// Only the disc gets sampled explicitly.
+pub struct Sun<E: Environment> {
+    environment: E,
+    direction: Vec3,
+    cos_radius: f32,
+    radiance: Rgb,
+}
+
+impl<E: Environment> Sun<E> {
+    /// `direction` points towards the sun, `angular_radius` is in radians.
+    pub fn new(environment: E, direction: Vec3, angular_radius: f32, radiance: Rgb) -> Self {
+        Sun {
+            environment: environment,
+            direction: na::normalize(&direction),
+            cos_radius: f32::cos(angular_radius),
+            radiance: radiance,
+        }
+    }
+
+    fn cone_pdf(&self) -> f32 {
+        1.0 / (2.0 * PI * (1.0 - self.cos_radius))
+    }
+}
+
+impl<E: Environment> Environment for Sun<E> {
+    fn radiance(&self, direction: Vec3) -> Rgb {
+        if na::dot(&direction, &self.direction) > self.cos_radius {
+            self.radiance
+        } else {
+            self.environment.radiance(direction)
+        }
+    }
+
+    fn sample(&self, sampler: &mut Sampler) -> Option<LightSample> {
+        // Uniformly distributed over the solid angle of the disc
+        let (u1, u2) = sampler.next_2d();
+        let cos_theta = 1.0 - u1 * (1.0 - self.cos_radius);
+        let sin_theta = f32::sqrt(f32::max(0.0, 1.0 - cos_theta * cos_theta));
+        let phi = 2.0 * PI * u2;
+        let local = Vec3::new(sin_theta * f32::cos(phi), sin_theta * f32::sin(phi), cos_theta);
+
+        Some(LightSample {
+            direction: sampling::to_world(local, self.direction),
+            distance: f32::INFINITY,
+            radiance: self.radiance,
+            pdf: self.cone_pdf(),
+            is_delta: false,
+        })
+    }
+
+    fn pdf(&self, direction: Vec3) -> f32 {
+        if na::dot(&direction, &self.direction) > self.cos_radius {
+            self.cone_pdf()
+        } else {
+            0.0
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! Writing uncompressed OpenEXR images with 32-bit float channels. Any
+//! number of layers can be stored in a single image, with their channels
+//! named like `layer.R`.
+
+use Rgb;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+pub struct Channel {
+    pub name: String,
+    /// The values in row major order.
+    pub values: Vec<f32>,
+}
+
+impl Channel {
+    pub fn new<S: Into<String>>(name: S, values: Vec<f32>) -> Self {
+        Channel {
+            name: name.into(),
+            values: values,
+        }
+    }
+}
+
+/// Splits the pixels up into the red, green and blue channels of a layer.
+/// The channels of the unnamed layer are just called `R`, `G` and `B`, which
+/// is what most applications show by default.
+pub fn rgb_layer(name: &str, pixels: &[Rgb]) -> Vec<Channel> {
+    let prefix = if name.is_empty() {
+        String::new()
+    } else {
+        format!("{}.", name)
+    };
+
+    vec![Channel::new(format!("{}R", prefix), pixels.iter().map(|p| p.red).collect()),
+         Channel::new(format!("{}G", prefix), pixels.iter().map(|p| p.green).collect()),
+         Channel::new(format!("{}B", prefix), pixels.iter().map(|p| p.blue).collect())]
+}
+
+fn push_u32(buffer: &mut Vec<u8>, value: u32) {
+    buffer.extend_from_slice(&[value as u8,
+                               (value >> 8) as u8,
+                               (value >> 16) as u8,
+                               (value >> 24) as u8]);
+}
+
+fn push_i32(buffer: &mut Vec<u8>, value: i32) {
+    push_u32(buffer, value as u32);
+}
+
+fn push_u64(buffer: &mut Vec<u8>, value: u64) {
+    push_u32(buffer, value as u32);
+    push_u32(buffer, (value >> 32) as u32);
+}
+
+fn push_f32(buffer: &mut Vec<u8>, value: f32) {
+    push_u32(buffer, value.to_bits());
+}
+
+fn push_str(buffer: &mut Vec<u8>, value: &str) {
+    buffer.extend_from_slice(value.as_bytes());
+    buffer.push(0);
+}
+
+fn push_attribute(buffer: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    push_str(buffer, name);
+    push_str(buffer, kind);
+    push_i32(buffer, value.len() as i32);
+    buffer.extend_from_slice(value);
+}
+
+pub fn write<W: Write>(mut writer: W,
+                       dimensions: (usize, usize),
+                       channels: &[Channel])
+                       -> io::Result<()> {
+    let (width, height) = dimensions;
+    if width == 0 || height == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Empty image"));
+    }
+
+    // Readers expect the channels in alphabetical order
+    let mut channels = channels.iter().collect::<Vec<_>>();
+    channels.sort_by(|a, b| a.name.cmp(&b.name));
+
+    for channel in &channels {
+        if channel.values.len() != width * height {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("Channel {} doesn't match the image size",
+                                              channel.name)));
+        }
+    }
+
+    let long_names = channels.iter().any(|channel| channel.name.len() > 31);
+
+    let mut header = Vec::new();
+    push_u32(&mut header, 20000630);
+    push_u32(&mut header, if long_names { 2 | 0x400 } else { 2 });
+
+    let mut list = Vec::new();
+    for channel in &channels {
+        push_str(&mut list, &channel.name);
+        // 32-bit float, not linear and without subsampling
+        push_i32(&mut list, 2);
+        list.extend_from_slice(&[0, 0, 0, 0]);
+        push_i32(&mut list, 1);
+        push_i32(&mut list, 1);
+    }
+    list.push(0);
+    push_attribute(&mut header, "channels", "chlist", &list);
+
+    push_attribute(&mut header, "compression", "compression", &[0]);
+
+    let mut window = Vec::new();
+    for &value in &[0, 0, width as i32 - 1, height as i32 - 1] {
+        push_i32(&mut window, value);
+    }
+    push_attribute(&mut header, "dataWindow", "box2i", &window);
+    push_attribute(&mut header, "displayWindow", "box2i", &window);
+
+    push_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+
+    let mut value = Vec::new();
+    push_f32(&mut value, 1.0);
+    push_attribute(&mut header, "pixelAspectRatio", "float", &value);
+    push_attribute(&mut header, "screenWindowWidth", "float", &value);
+
+    let mut value = Vec::new();
+    push_f32(&mut value, 0.0);
+    push_f32(&mut value, 0.0);
+    push_attribute(&mut header, "screenWindowCenter", "v2f", &value);
+
+    header.push(0);
+
+    // Without compression every scanline is a chunk of its own, which is
+    // located through a table of offsets from the start of the file
+    let chunk_size = 8 + 4 * width * channels.len();
+    let table_end = header.len() + 8 * height;
+    for y in 0..height {
+        push_u64(&mut header, (table_end + y * chunk_size) as u64);
+    }
+    try!(writer.write_all(&header));
+
+    let mut chunk = Vec::with_capacity(chunk_size);
+    for y in 0..height {
+        chunk.clear();
+        push_i32(&mut chunk, y as i32);
+        push_i32(&mut chunk, (chunk_size - 8) as i32);
+        for channel in &channels {
+            for &value in &channel.values[y * width..(y + 1) * width] {
+                push_f32(&mut chunk, value);
+            }
+        }
+        try!(writer.write_all(&chunk));
+    }
+
+    Ok(())
+}
+
+pub fn save<P: AsRef<Path>>(path: P,
+                            dimensions: (usize, usize),
+                            channels: &[Channel])
+                            -> io::Result<()> {
+    let file = try!(File::create(path));
+    let mut writer = BufWriter::new(file);
+    try!(write(&mut writer, dimensions, channels));
+    writer.flush()
+}
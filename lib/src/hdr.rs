@@ -0,0 +1,268 @@
+//! Reading and writing Radiance RGBE (.hdr) images.
+
+use Rgb;
+use std::f32;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+fn invalid<T>(message: &str) -> io::Result<T> {
+    Err(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+fn read_line<R: BufRead>(reader: &mut R, line: &mut String) -> io::Result<()> {
+    line.clear();
+    if try!(reader.read_line(line)) == 0 {
+        invalid("Unexpected end of the header")
+    } else {
+        Ok(())
+    }
+}
+
+fn rgbe_to_rgb(rgbe: [u8; 4]) -> Rgb {
+    if rgbe[3] == 0 {
+        Rgb::new(0.0, 0.0, 0.0)
+    } else {
+        let factor = f32::powi(2.0, rgbe[3] as i32 - (128 + 8));
+        Rgb::new((rgbe[0] as f32 + 0.5) * factor,
+                 (rgbe[1] as f32 + 0.5) * factor,
+                 (rgbe[2] as f32 + 0.5) * factor)
+    }
+}
+
+fn rgb_to_rgbe(rgb: Rgb) -> [u8; 4] {
+    let (r, g, b) = (f32::max(rgb.red, 0.0), f32::max(rgb.green, 0.0), f32::max(rgb.blue, 0.0));
+    let max = f32::max(r, f32::max(g, b));
+    if !(max >= 1e-32) {
+        return [0, 0, 0, 0];
+    }
+
+    // The largest component needs a mantissa in [0.5, 1)
+    let mut exponent = f32::floor(f32::log2(max)) as i32 + 1;
+    if max * f32::powi(2.0, -exponent) >= 1.0 {
+        exponent += 1;
+    }
+    let exponent = i32::min(exponent, 127);
+    let factor = 256.0 * f32::powi(2.0, -exponent);
+
+    [f32::min(r * factor, 255.0) as u8,
+     f32::min(g * factor, 255.0) as u8,
+     f32::min(b * factor, 255.0) as u8,
+     (exponent + 128) as u8]
+}
+
+fn read_byte<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut byte = [0];
+    try!(reader.read_exact(&mut byte));
+    Ok(byte[0])
+}
+
+/// Reads a scanline of `width` pixels into `scanline`. The buffer only grows
+/// as the pixels are actually read, so a bogus width in the header can't
+/// allocate more memory than the file contains.
+fn read_scanline<R: Read>(reader: &mut R,
+                          width: usize,
+                          scanline: &mut Vec<[u8; 4]>)
+                          -> io::Result<()> {
+    let mut header = [0; 4];
+    try!(reader.read_exact(&mut header));
+
+    let is_run_length_encoded = width >= 8 && width < 0x8000 && header[0] == 2 &&
+                                header[1] == 2 && header[2] & 0x80 == 0;
+
+    scanline.clear();
+    if !is_run_length_encoded {
+        scanline.push(header);
+        for _ in 1..width {
+            let mut pixel = [0; 4];
+            try!(reader.read_exact(&mut pixel));
+            scanline.push(pixel);
+        }
+        return Ok(());
+    }
+
+    if ((header[2] as usize) << 8 | header[3] as usize) != width {
+        return invalid("Scanline width mismatch");
+    }
+    scanline.resize(width, [0; 4]);
+
+    // Every channel is run length encoded separately
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = try!(read_byte(reader)) as usize;
+            if count > 128 {
+                let count = count - 128;
+                if x + count > width {
+                    return invalid("Run exceeds the scanline");
+                }
+                let value = try!(read_byte(reader));
+                for pixel in &mut scanline[x..x + count] {
+                    pixel[channel] = value;
+                }
+                x += count;
+            } else {
+                if count == 0 || x + count > width {
+                    return invalid("Run exceeds the scanline");
+                }
+                for pixel in &mut scanline[x..x + count] {
+                    pixel[channel] = try!(read_byte(reader));
+                }
+                x += count;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Appends the run length encoding of a single channel of a scanline.
+fn encode_channel(values: &[u8], buffer: &mut Vec<u8>) {
+    let len = values.len();
+    let mut x = 0;
+
+    while x < len {
+        // Find the next run that is long enough to be worth encoding
+        let mut run_start = x;
+        let mut run_length = 0;
+        while run_start < len {
+            run_length = 1;
+            while run_start + run_length < len && run_length < 127 &&
+                  values[run_start + run_length] == values[run_start] {
+                run_length += 1;
+            }
+            if run_length >= 4 {
+                break;
+            }
+            run_start += run_length;
+        }
+
+        // Everything up to the run gets stored literally
+        while x < run_start {
+            let count = usize::min(128, run_start - x);
+            buffer.push(count as u8);
+            buffer.extend_from_slice(&values[x..x + count]);
+            x += count;
+        }
+
+        if run_start < len {
+            buffer.push((128 + run_length) as u8);
+            buffer.push(values[run_start]);
+            x = run_start + run_length;
+        }
+    }
+}
+
+/// Reads an image with the standard orientation of rows from top to bottom.
+/// Returns its dimensions and the pixels in row major order.
+pub fn read<R: BufRead>(mut reader: R) -> io::Result<((usize, usize), Vec<Rgb>)> {
+    let mut line = String::new();
+
+    try!(read_line(&mut reader, &mut line));
+    if !line.starts_with("#?") {
+        return invalid("Not a Radiance HDR image");
+    }
+
+    loop {
+        try!(read_line(&mut reader, &mut line));
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+            return invalid("Unsupported pixel format");
+        }
+    }
+
+    try!(read_line(&mut reader, &mut line));
+    let dimensions = {
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next(), words.next(), words.next()) {
+            (Some("-Y"), Some(height), Some("+X"), Some(width)) => {
+                match (width.parse(), height.parse()) {
+                    (Ok(width), Ok(height)) => (width, height),
+                    _ => return invalid("Invalid resolution"),
+                }
+            }
+            _ => return invalid("Unsupported image orientation"),
+        }
+    };
+
+    let (width, height) = dimensions;
+    if width == 0 || height == 0 {
+        return invalid("Empty image");
+    }
+    let len = match width.checked_mul(height) {
+        Some(len) => len,
+        None => return invalid("Image too large"),
+    };
+    // Don't trust the header with a huge allocation up front
+    let mut pixels = Vec::with_capacity(usize::min(len, 1 << 22));
+    let mut scanline = Vec::new();
+
+    for _ in 0..height {
+        try!(read_scanline(&mut reader, width, &mut scanline));
+        pixels.extend(scanline.iter().map(|&rgbe| rgbe_to_rgb(rgbe)));
+    }
+
+    Ok((dimensions, pixels))
+}
+
+pub fn open<P: AsRef<Path>>(path: P) -> io::Result<((usize, usize), Vec<Rgb>)> {
+    let file = try!(File::open(path));
+    read(BufReader::new(file))
+}
+
+/// Writes the pixels, which are in row major order, run length encoded.
+pub fn write<W: Write>(mut writer: W,
+                       dimensions: (usize, usize),
+                       pixels: &[Rgb])
+                       -> io::Result<()> {
+    let (width, height) = dimensions;
+    if width == 0 || height == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Empty image"));
+    }
+    if pixels.len() != width * height {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  "The pixels don't match the image size"));
+    }
+
+    try!(write!(writer,
+                "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+                height,
+                width));
+
+    let mut buffer = Vec::new();
+    let mut channel = vec![0; width];
+
+    for row in pixels.chunks(width) {
+        buffer.clear();
+        let scanline = row.iter().map(|&pixel| rgb_to_rgbe(pixel)).collect::<Vec<_>>();
+
+        if width < 8 || width >= 0x8000 {
+            // These widths can't be run length encoded
+            for rgbe in &scanline {
+                buffer.extend_from_slice(rgbe);
+            }
+        } else {
+            buffer.extend_from_slice(&[2, 2, (width >> 8) as u8, width as u8]);
+            for index in 0..4 {
+                for (value, rgbe) in channel.iter_mut().zip(&scanline) {
+                    *value = rgbe[index];
+                }
+                encode_channel(&channel, &mut buffer);
+            }
+        }
+
+        try!(writer.write_all(&buffer));
+    }
+
+    Ok(())
+}
+
+pub fn save<P: AsRef<Path>>(path: P, dimensions: (usize, usize), pixels: &[Rgb]) -> io::Result<()> {
+    let file = try!(File::create(path));
+    let mut writer = BufWriter::new(file);
+    try!(write(&mut writer, dimensions, pixels));
+    writer.flush()
+}
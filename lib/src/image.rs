@@ -2,10 +2,24 @@ use std::ops::{Index, IndexMut};
 use std::slice;
 use prelude::*;
 use RgbaImage;
+use hdr;
+use exr;
+use tone_mapping::{self, ToneMapper, Operator, Transfer};
+use std::io;
+use std::path::Path;
+use std::f32;
 
+/// The mean of all the samples of every pixel. The sample counts and the
+/// variance of the luminance are tracked per pixel as well, so that the
+/// sampling can focus on the noisy pixels.
+#[derive(Clone)]
 pub struct Image {
     pub dimensions: (usize, usize),
     field: Vec<Rgb>,
+    sample_counts: Vec<u32>,
+    /// The sum of the squared differences of the luminance to the mean, see
+    /// Welford's online algorithm.
+    squared_deviations: Vec<f32>,
 }
 
 impl Index<(usize, usize)> for Image {
@@ -31,9 +45,82 @@ impl Image {
         Image {
             dimensions: (nx, ny),
             field: vec![Rgb::new(0.0, 0.0, 0.0); nx * ny],
+            sample_counts: vec![0; nx * ny],
+            squared_deviations: vec![0.0; nx * ny],
         }
     }
 
+    /// Forgets all the samples.
+    pub fn clear(&mut self) {
+        for value in &mut self.field {
+            *value = Rgb::new(0.0, 0.0, 0.0);
+        }
+        for count in &mut self.sample_counts {
+            *count = 0;
+        }
+        for deviation in &mut self.squared_deviations {
+            *deviation = 0.0;
+        }
+    }
+
+    /// Adds a sample to the running mean and variance of a pixel.
+    pub fn add_sample(&mut self, (x, y): (usize, usize), sample: Rgb) {
+        let index = x + self.dimensions.0 * y;
+        let count = self.sample_counts[index] + 1;
+        let old_mean = self.field[index];
+        let new_mean = old_mean + (sample - old_mean) / count as f32;
+
+        let luminance = tone_mapping::luminance(sample);
+        self.squared_deviations[index] += (luminance - tone_mapping::luminance(old_mean)) *
+                                          (luminance - tone_mapping::luminance(new_mean));
+        self.field[index] = new_mean;
+        self.sample_counts[index] = count;
+    }
+
+    pub fn sample_count(&self, (x, y): (usize, usize)) -> u32 {
+        self.sample_counts[x + self.dimensions.0 * y]
+    }
+
+    /// The sample variance of the luminance of a pixel.
+    pub fn variance(&self, (x, y): (usize, usize)) -> f32 {
+        let index = x + self.dimensions.0 * y;
+        let count = self.sample_counts[index];
+        if count < 2 {
+            f32::INFINITY
+        } else {
+            self.squared_deviations[index] / (count - 1) as f32
+        }
+    }
+
+    /// Estimates how far the luminance of a pixel is off, relative to the
+    /// luminance itself. Pixels darker than 0.05 count as 0.05, as noise in
+    /// almost black pixels is hardly visible.
+    pub fn relative_error(&self, coord: (usize, usize)) -> f32 {
+        let count = self.sample_count(coord) as f32;
+        let standard_error = f32::sqrt(self.variance(coord) / count);
+        standard_error / f32::max(tone_mapping::luminance(self[coord]), 0.05)
+    }
+
+    /// Shows the sample count of every pixel, from black for none to white
+    /// for the most samples any pixel got.
+    pub fn sample_count_image(&self) -> RgbaImage {
+        let (nx, ny) = self.dimensions;
+        let max = self.sample_counts.iter().cloned().max().unwrap_or(0);
+        let mut image = RgbaImage::new(nx as u32, ny as u32);
+
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            let count = self.sample_counts[x as usize + nx * y as usize];
+            let value = if max > 0 {
+                (255.0 * count as f32 / max as f32) as u8
+            } else {
+                0
+            };
+            pixel.data = [value, value, value, 0xFF];
+        }
+
+        image
+    }
+
     pub fn contains(&self, (x, y): (usize, usize)) -> bool {
         let (nx, ny) = self.dimensions;
         x < nx && y < ny
@@ -88,18 +175,36 @@ impl Image {
         }
     }
 
+    /// The linear radiance of all the pixels in row major order.
+    pub fn pixels(&self) -> &[Rgb] {
+        &self.field
+    }
+
+    /// Saves the radiance without any loss as an OpenEXR image.
+    pub fn save_exr<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        exr::save(path, self.dimensions, &exr::rgb_layer("", &self.field))
+    }
+
+    /// Saves the radiance as a Radiance HDR image, which stores it with a
+    /// shared exponent and 8-bit mantissas.
+    pub fn save_hdr<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        hdr::save(path, self.dimensions, &self.field)
+    }
+
     pub fn to_rgba_image(&self, gamma: f32) -> RgbaImage {
-        use std::f32;
+        self.tone_map(&ToneMapper::new(Operator::Clamp, 0.0, Transfer::Gamma(gamma)))
+    }
 
+    pub fn tone_map(&self, tone_mapper: &ToneMapper) -> RgbaImage {
         let (nx, ny) = self.dimensions;
         let mut image = RgbaImage::new(nx as u32, ny as u32);
 
         for (x, y, pixel) in image.enumerate_pixels_mut() {
-            let value = self[(x as usize, y as usize)];
-            let (r, g, b) = (255.0 * f32::powf(value.red.saturate(), 1.0 / gamma),
-                             255.0 * f32::powf(value.green.saturate(), 1.0 / gamma),
-                             255.0 * f32::powf(value.blue.saturate(), 1.0 / gamma));
-            pixel.data = [r as u8, g as u8, b as u8, 0xFF];
+            let value = tone_mapper.map(self[(x as usize, y as usize)]);
+            pixel.data = [(255.0 * value.red) as u8,
+                          (255.0 * value.green) as u8,
+                          (255.0 * value.blue) as u8,
+                          0xFF];
         }
 
         image
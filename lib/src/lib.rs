@@ -12,13 +12,36 @@ pub mod raytracer;
 pub mod collision;
 pub mod brdf;
 pub mod clamp;
+pub mod aabb;
+pub mod bvh;
+pub mod obj;
+pub mod light;
+pub mod environment;
+pub mod hdr;
+pub mod exr;
+pub mod tone_mapping;
+mod random;
+pub mod sampler;
+pub mod tile;
+pub mod progress;
+pub mod aov;
+pub mod denoise;
+pub mod transform;
 
 pub use entity::Entity;
 pub use ray::Ray;
-pub use raytracer::{RayTracer, SamplingConfig};
+pub use raytracer::{RayTracer, SamplingConfig, AdaptiveSampling};
 pub use collision::Collision;
 pub use brdf::Brdf;
 pub use entity::camera::Camera;
+pub use aabb::Aabb;
+pub use light::Light;
+pub use environment::Environment;
+pub use tone_mapping::ToneMapper;
+pub use sampler::Sampler;
+pub use denoise::Denoiser;
+pub use image::Image;
+pub use transform::Transform;
 
 pub type Vec3 = nalgebra::Vec3<f32>;
 pub type Rgb = palette::Rgb<f32>;
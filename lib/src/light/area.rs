@@ -0,0 +1,88 @@
+use prelude::*;
+use super::{Light, LightSample};
+use ray::Ray;
+use nalgebra as na;
+use sampler::Sampler;
+use std::f32;
+
+/// A parallelogram that emits light to the side its normal
+/// `cross(edge_u, edge_v)` is facing.
+pub struct AreaLight {
+    corner: Vec3,
+    edge_u: Vec3,
+    edge_v: Vec3,
+    normal: Vec3,
+    area: f32,
+    radiance: Rgb,
+}
+
+impl AreaLight {
+    pub fn new(corner: Vec3, edge_u: Vec3, edge_v: Vec3, radiance: Rgb) -> Self {
+        let cross = na::cross(&edge_u, &edge_v);
+        AreaLight {
+            corner: corner,
+            edge_u: edge_u,
+            edge_v: edge_v,
+            normal: na::normalize(&cross),
+            area: na::norm(&cross),
+            radiance: radiance,
+        }
+    }
+
+    /// Converts the uniform density over the area to a density over the
+    /// solid angle seen from the shaded position.
+    fn pdf(&self, distance: f32, direction: Vec3) -> f32 {
+        let cos_light = -na::dot(&direction, &self.normal);
+        if cos_light <= 0.0 {
+            0.0
+        } else {
+            distance * distance / (cos_light * self.area)
+        }
+    }
+}
+
+impl Light for AreaLight {
+    fn sample(&self, position: Vec3, sampler: &mut Sampler) -> Option<LightSample> {
+        let (u, v) = sampler.next_2d();
+        let point = self.corner + self.edge_u * u + self.edge_v * v;
+        let to_light = point - position;
+        let distance = na::norm(&to_light);
+        let direction = to_light / distance;
+
+        let pdf = self.pdf(distance, direction);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction: direction,
+            distance: distance,
+            radiance: self.radiance,
+            pdf: pdf,
+            is_delta: false,
+        })
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<(f32, Rgb, f32)> {
+        let denominator = na::dot(&ray.direction, &self.normal);
+        if denominator >= 0.0 {
+            // Parallel to the light or hitting its back
+            return None;
+        }
+
+        let distance = na::dot(&(self.corner - ray.origin), &self.normal) / denominator;
+        if distance <= 0.0 {
+            return None;
+        }
+
+        let offset = ray.origin + ray.direction * distance - self.corner;
+        let u = na::dot(&na::cross(&offset, &self.edge_v), &self.normal) / self.area;
+        let v = na::dot(&na::cross(&self.edge_u, &offset), &self.normal) / self.area;
+
+        if u < 0.0 || u > 1.0 || v < 0.0 || v > 1.0 {
+            return None;
+        }
+
+        Some((distance, self.radiance, self.pdf(distance, ray.direction)))
+    }
+}
@@ -0,0 +1,32 @@
+use prelude::*;
+use super::{Light, LightSample};
+use nalgebra as na;
+use sampler::Sampler;
+use std::f32;
+
+pub struct DirectionalLight {
+    direction: Vec3,
+    irradiance: Rgb,
+}
+
+impl DirectionalLight {
+    /// `direction` is the direction the light travels in.
+    pub fn new(direction: Vec3, irradiance: Rgb) -> Self {
+        DirectionalLight {
+            direction: na::normalize(&direction),
+            irradiance: irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _: Vec3, _: &mut Sampler) -> Option<LightSample> {
+        Some(LightSample {
+            direction: self.direction * -1.0,
+            distance: f32::INFINITY,
+            radiance: self.irradiance,
+            pdf: 1.0,
+            is_delta: true,
+        })
+    }
+}
@@ -0,0 +1,36 @@
+use prelude::*;
+use ray::Ray;
+use sampler::Sampler;
+
+pub struct LightSample {
+    /// Points from the shaded position towards the light.
+    pub direction: Vec3,
+    pub distance: f32,
+    pub radiance: Rgb,
+    /// Solid angle density of the direction. Lights that can only be reached
+    /// through a single direction (`is_delta`) use a density of 1.
+    pub pdf: f32,
+    pub is_delta: bool,
+}
+
+pub trait Light {
+    fn sample(&self, position: Vec3, sampler: &mut Sampler) -> Option<LightSample>;
+
+    /// Checks whether the ray hits the surface of the light. Returns the
+    /// distance, the emitted radiance and the density `sample` would've
+    /// picked the ray's direction with. Lights without a surface can't be
+    /// hit.
+    fn intersect(&self, _ray: &Ray) -> Option<(f32, Rgb, f32)> {
+        None
+    }
+}
+
+pub mod point;
+pub mod directional;
+pub mod spot;
+pub mod area;
+
+pub use self::point::PointLight;
+pub use self::directional::DirectionalLight;
+pub use self::spot::SpotLight;
+pub use self::area::AreaLight;
@@ -0,0 +1,35 @@
+use prelude::*;
+use super::{Light, LightSample};
+use nalgebra as na;
+use sampler::Sampler;
+use std::f32;
+
+pub struct PointLight {
+    position: Vec3,
+    intensity: Rgb,
+}
+
+impl PointLight {
+    pub fn new(position: Vec3, intensity: Rgb) -> Self {
+        PointLight {
+            position: position,
+            intensity: intensity,
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, position: Vec3, _: &mut Sampler) -> Option<LightSample> {
+        let to_light = self.position - position;
+        let distance2 = na::sqnorm(&to_light);
+        let distance = f32::sqrt(distance2);
+
+        Some(LightSample {
+            direction: to_light / distance,
+            distance: distance,
+            radiance: self.intensity / distance2,
+            pdf: 1.0,
+            is_delta: true,
+        })
+    }
+}
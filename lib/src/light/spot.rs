@@ -0,0 +1,66 @@
+use prelude::*;
+use super::{Light, LightSample};
+use nalgebra as na;
+use sampler::Sampler;
+use std::f32;
+
+pub struct SpotLight {
+    position: Vec3,
+    direction: Vec3,
+    intensity: Rgb,
+    cos_inner_angle: f32,
+    cos_outer_angle: f32,
+}
+
+impl SpotLight {
+    /// The light falls off smoothly between the inner and the outer angle,
+    /// which are measured from the direction the spot light is pointing at.
+    pub fn new(position: Vec3,
+               direction: Vec3,
+               intensity: Rgb,
+               inner_angle: f32,
+               outer_angle: f32)
+               -> Self {
+        SpotLight {
+            position: position,
+            direction: na::normalize(&direction),
+            intensity: intensity,
+            cos_inner_angle: f32::cos(inner_angle),
+            cos_outer_angle: f32::cos(outer_angle),
+        }
+    }
+
+    fn falloff(&self, cos_angle: f32) -> f32 {
+        if cos_angle >= self.cos_inner_angle {
+            1.0
+        } else if cos_angle <= self.cos_outer_angle {
+            0.0
+        } else {
+            let t = (cos_angle - self.cos_outer_angle) /
+                    (self.cos_inner_angle - self.cos_outer_angle);
+            t * t * (3.0 - 2.0 * t)
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, position: Vec3, _: &mut Sampler) -> Option<LightSample> {
+        let to_light = self.position - position;
+        let distance2 = na::sqnorm(&to_light);
+        let distance = f32::sqrt(distance2);
+        let direction = to_light / distance;
+
+        let falloff = self.falloff(na::dot(&(direction * -1.0), &self.direction));
+        if falloff <= 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction: direction,
+            distance: distance,
+            radiance: self.intensity * (falloff / distance2),
+            pdf: 1.0,
+            is_delta: true,
+        })
+    }
+}
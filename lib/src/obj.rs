@@ -0,0 +1,569 @@
+use prelude::*;
+use entity::{Mesh, Vertices};
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::str::{FromStr, SplitWhitespace};
+
+#[derive(Debug)]
+pub enum Error {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        file: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl Error {
+    fn io(path: &Path, error: io::Error) -> Self {
+        Error::Io {
+            path: path.to_owned(),
+            error: error,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io { ref path, ref error } => write!(f, "{}: {}", path.display(), error),
+            Error::Parse { ref file, line, ref message } => {
+                write!(f, "{}:{}: {}", file.display(), line, message)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io { .. } => "I/O error while reading an OBJ or MTL file",
+            Error::Parse { ref message, .. } => message,
+        }
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VertexIndices {
+    pub position: usize,
+    pub uv: Option<usize>,
+    pub normal: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Group {
+    pub name: String,
+    pub material: Option<String>,
+    pub triangles: Vec<[VertexIndices; 3]>,
+}
+
+/// The contents of an OBJ file. Faces with more than three vertices are
+/// already split up into triangle fans, so they are expected to be convex.
+#[derive(Clone, Debug)]
+pub struct Obj {
+    pub positions: Vec<Vec3>,
+    pub uvs: Vec<(f32, f32)>,
+    pub normals: Vec<Vec3>,
+    pub groups: Vec<Group>,
+    pub material_libraries: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub name: String,
+    pub ambient: Rgb,
+    pub diffuse: Rgb,
+    pub specular: Rgb,
+    pub emissive: Rgb,
+    pub shininess: f32,
+    pub roughness: Option<f32>,
+    pub dissolve: f32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            name: String::new(),
+            ambient: Rgb::new(0.0, 0.0, 0.0),
+            diffuse: Rgb::new(0.8, 0.8, 0.8),
+            specular: Rgb::new(0.0, 0.0, 0.0),
+            emissive: Rgb::new(0.0, 0.0, 0.0),
+            shininess: 0.0,
+            roughness: None,
+            dissolve: 1.0,
+        }
+    }
+}
+
+struct Line<'a> {
+    file: &'a Path,
+    number: usize,
+}
+
+impl<'a> Line<'a> {
+    fn error<T, S: Into<String>>(&self, message: S) -> Result<T> {
+        Err(Error::Parse {
+            file: self.file.to_path_buf(),
+            line: self.number,
+            message: message.into(),
+        })
+    }
+
+    fn number<T: FromStr>(&self, word: Option<&str>, what: &str) -> Result<T> {
+        match word {
+            Some(word) => {
+                match word.parse() {
+                    Ok(value) => Ok(value),
+                    Err(_) => self.error(format!("Invalid {}: {}", what, word)),
+                }
+            }
+            None => self.error(format!("Missing {}", what)),
+        }
+    }
+
+    fn optional_number<T: FromStr>(&self, word: Option<&str>, what: &str) -> Result<Option<T>> {
+        match word {
+            Some(word) => self.number(Some(word), what).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn vec3(&self, words: &mut SplitWhitespace) -> Result<Vec3> {
+        let x = try!(self.number(words.next(), "x coordinate"));
+        let y = try!(self.number(words.next(), "y coordinate"));
+        let z = try!(self.number(words.next(), "z coordinate"));
+        Ok(Vec3::new(x, y, z))
+    }
+
+    fn rgb(&self, words: &mut SplitWhitespace) -> Result<Rgb> {
+        let r = try!(self.number(words.next(), "red component"));
+        // A single value means all the components are the same
+        let g = try!(self.optional_number(words.next(), "green component")).unwrap_or(r);
+        let b = try!(self.optional_number(words.next(), "blue component")).unwrap_or(r);
+        Ok(Rgb::new(r, g, b))
+    }
+
+    fn rest(&self, words: SplitWhitespace, what: &str) -> Result<String> {
+        let rest = words.collect::<Vec<_>>().join(" ");
+        if rest.is_empty() {
+            self.error(format!("Missing {}", what))
+        } else {
+            Ok(rest)
+        }
+    }
+
+    fn index(&self, word: &str, count: usize, what: &str) -> Result<usize> {
+        let index: isize = try!(self.number(Some(word), what));
+        let resolved = if index > 0 {
+            index - 1
+        } else {
+            // Negative indices are relative to the end of the list
+            count as isize + index
+        };
+        if index == 0 || resolved < 0 || resolved >= count as isize {
+            self.error(format!("Out of range {}: {}", what, index))
+        } else {
+            Ok(resolved as usize)
+        }
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    match line.find('#') {
+        Some(index) => &line[..index],
+        None => line,
+    }
+}
+
+impl Obj {
+    pub fn parse<R: BufRead>(reader: R, file: &Path) -> Result<Obj> {
+        let mut obj = Obj {
+            positions: Vec::new(),
+            uvs: Vec::new(),
+            normals: Vec::new(),
+            groups: Vec::new(),
+            material_libraries: Vec::new(),
+        };
+
+        let mut group_name = String::new();
+        let mut material = None;
+
+        for (index, text) in reader.lines().enumerate() {
+            let text = try!(text.map_err(|error| Error::io(file, error)));
+            let line = Line {
+                file: file,
+                number: index + 1,
+            };
+            let mut words = strip_comment(&text).split_whitespace();
+
+            match words.next() {
+                Some("v") => obj.positions.push(try!(line.vec3(&mut words))),
+                Some("vn") => obj.normals.push(try!(line.vec3(&mut words))),
+                Some("vt") => {
+                    let u = try!(line.number(words.next(), "u coordinate"));
+                    let v = try!(line.optional_number(words.next(), "v coordinate"));
+                    obj.uvs.push((u, v.unwrap_or(0.0)));
+                }
+                Some("f") => {
+                    let mut vertices = Vec::new();
+                    for word in words {
+                        vertices.push(try!(obj.parse_vertex(&line, word)));
+                    }
+
+                    if vertices.len() < 3 {
+                        return line.error("A face needs at least three vertices");
+                    }
+
+                    let needs_group = obj.groups.last().map_or(true, |group| {
+                        group.name != group_name || group.material != material
+                    });
+                    if needs_group {
+                        obj.groups.push(Group {
+                            name: group_name.clone(),
+                            material: material.clone(),
+                            triangles: Vec::new(),
+                        });
+                    }
+                    let group = obj.groups.last_mut().unwrap();
+
+                    for i in 1..vertices.len() - 1 {
+                        group.triangles.push([vertices[0], vertices[i], vertices[i + 1]]);
+                    }
+                }
+                Some("o") | Some("g") => {
+                    group_name = words.collect::<Vec<_>>().join(" ");
+                }
+                Some("usemtl") => {
+                    material = Some(try!(line.rest(words, "material name")));
+                }
+                Some("mtllib") => {
+                    let libraries = words.map(String::from).collect::<Vec<_>>();
+                    if libraries.is_empty() {
+                        return line.error("Missing material library");
+                    }
+                    obj.material_libraries.extend(libraries);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(obj)
+    }
+
+    fn parse_vertex(&self, line: &Line, word: &str) -> Result<VertexIndices> {
+        let mut parts = word.split('/');
+
+        let position = match parts.next() {
+            Some(part) => try!(line.index(part, self.positions.len(), "vertex index")),
+            None => return line.error(format!("Invalid face vertex: {}", word)),
+        };
+
+        let uv = match parts.next() {
+            Some("") | None => None,
+            Some(part) => Some(try!(line.index(part, self.uvs.len(), "texture coordinate index"))),
+        };
+
+        let normal = match parts.next() {
+            Some("") | None => None,
+            Some(part) => Some(try!(line.index(part, self.normals.len(), "normal index"))),
+        };
+
+        if parts.next().is_some() {
+            return line.error(format!("Invalid face vertex: {}", word));
+        }
+
+        Ok(VertexIndices {
+            position: position,
+            uv: uv,
+            normal: normal,
+        })
+    }
+
+    /// Creates a mesh for every group of faces that share the same object or
+    /// group name and material. Faces that refer to a material that isn't
+    /// known use the default material instead.
+    pub fn into_meshes<BrdfType, F>(self,
+                                    materials: &HashMap<String, Material>,
+                                    mut brdf_for: F)
+                                    -> Vec<Mesh<BrdfType>>
+        where BrdfType: Brdf + 'static,
+              F: FnMut(&Material) -> BrdfType
+    {
+        let default_material = Material::default();
+        let positions = self.positions;
+        let uvs = self.uvs;
+        let normals = self.normals;
+
+        self.groups
+            .into_iter()
+            .map(|group| {
+                let material = group.material
+                                    .as_ref()
+                                    .and_then(|name| materials.get(name))
+                                    .unwrap_or(&default_material);
+
+                let (mut vertices, indices) = mesh_arrays(&group.triangles,
+                                                          &positions,
+                                                          &uvs,
+                                                          &normals);
+                vertices.compute_tangents(&indices);
+                Mesh::from_arrays(vertices, indices, brdf_for(material))
+            })
+            .collect()
+    }
+}
+
+/// Gives every distinct combination of position, texture coordinates and
+/// normal a vertex of its own. Normals and texture coordinates only get used
+/// if all the vertices have them.
+fn mesh_arrays(triangles: &[[VertexIndices; 3]],
+               positions: &[Vec3],
+               uvs: &[(f32, f32)],
+               normals: &[Vec3])
+               -> (Vertices, Vec<[usize; 3]>) {
+    let mut vertex_indices = Vec::new();
+    let mut merged = HashMap::new();
+    let indices = triangles.iter()
+                           .map(|triangle| {
+                               let mut face = [0; 3];
+                               for (index, vertex) in face.iter_mut().zip(triangle) {
+                                   let next_index = vertex_indices.len();
+                                   *index = *merged.entry(*vertex).or_insert_with(|| {
+                                       vertex_indices.push(*vertex);
+                                       next_index
+                                   });
+                               }
+                               face
+                           })
+                           .collect();
+
+    let mut vertices = Vertices::new(vertex_indices.iter()
+                                                   .map(|vertex| positions[vertex.position])
+                                                   .collect());
+    if vertex_indices.iter().all(|vertex| vertex.normal.is_some()) {
+        vertices.normals = Some(vertex_indices.iter()
+                                              .map(|vertex| normals[vertex.normal.unwrap()])
+                                              .collect());
+    }
+    if vertex_indices.iter().all(|vertex| vertex.uv.is_some()) {
+        vertices.uvs = Some(vertex_indices.iter()
+                                          .map(|vertex| uvs[vertex.uv.unwrap()])
+                                          .collect());
+    }
+    (vertices, indices)
+}
+
+pub fn parse_mtl<R: BufRead>(reader: R, file: &Path) -> Result<Vec<Material>> {
+    let mut materials: Vec<Material> = Vec::new();
+
+    for (index, text) in reader.lines().enumerate() {
+        let text = try!(text.map_err(|error| Error::io(file, error)));
+        let line = Line {
+            file: file,
+            number: index + 1,
+        };
+        let mut words = strip_comment(&text).split_whitespace();
+
+        let statement = match words.next() {
+            Some(statement) => statement,
+            None => continue,
+        };
+
+        if statement == "newmtl" {
+            materials.push(Material {
+                name: try!(line.rest(words, "material name")),
+                ..Material::default()
+            });
+            continue;
+        }
+
+        let material = match materials.last_mut() {
+            Some(material) => material,
+            None => return line.error(format!("{} before the first newmtl", statement)),
+        };
+
+        match statement {
+            "Ka" => material.ambient = try!(line.rgb(&mut words)),
+            "Kd" => material.diffuse = try!(line.rgb(&mut words)),
+            "Ks" => material.specular = try!(line.rgb(&mut words)),
+            "Ke" => material.emissive = try!(line.rgb(&mut words)),
+            "Ns" => material.shininess = try!(line.number(words.next(), "shininess")),
+            "Pr" => material.roughness = Some(try!(line.number(words.next(), "roughness"))),
+            "d" => material.dissolve = try!(line.number(words.next(), "dissolve")),
+            "Tr" => {
+                let transparency: f32 = try!(line.number(words.next(), "transparency"));
+                material.dissolve = 1.0 - transparency;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+/// Loads an OBJ file along with the MTL libraries it references, which get
+/// looked up relative to the OBJ file. The BRDF of each of the resulting
+/// meshes is created from its material by `brdf_for`.
+pub fn load<P, BrdfType, F>(path: P, brdf_for: F) -> Result<Vec<Mesh<BrdfType>>>
+    where P: AsRef<Path>,
+          BrdfType: Brdf + 'static,
+          F: FnMut(&Material) -> BrdfType
+{
+    let path = path.as_ref();
+    let file = try!(File::open(path).map_err(|error| Error::io(path, error)));
+    let obj = try!(Obj::parse(BufReader::new(file), path));
+
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let mut materials = HashMap::new();
+
+    for library in &obj.material_libraries {
+        let library_path = directory.join(library);
+        let file = try!(File::open(&library_path)
+                            .map_err(|error| Error::io(&library_path, error)));
+        for material in try!(parse_mtl(BufReader::new(file), &library_path)) {
+            materials.insert(material.name.clone(), material);
+        }
+    }
+
+    Ok(obj.into_meshes(&materials, brdf_for))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brdf::Lambert;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+
+    fn parse(source: &str) -> Result<Obj> {
+        Obj::parse(source.as_bytes(), Path::new("test.obj"))
+    }
+
+    fn vertex(position: usize, uv: Option<usize>, normal: Option<usize>) -> VertexIndices {
+        VertexIndices {
+            position: position,
+            uv: uv,
+            normal: normal,
+        }
+    }
+
+    #[test]
+    fn quad_becomes_two_triangles() {
+        let obj = parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n").unwrap();
+        assert_eq!(obj.groups.len(), 1);
+        let triangles = &obj.groups[0].triangles;
+        let corner = |position| vertex(position, None, None);
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(triangles[0], [corner(0), corner(1), corner(2)]);
+        assert_eq!(triangles[1], [corner(0), corner(2), corner(3)]);
+    }
+
+    #[test]
+    fn negative_indices_count_from_the_end() {
+        let obj = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\n\
+                         vn 0 0 1\nf -3/-3/-1 -2/-2/-1 -1/-1/-1\n")
+                      .unwrap();
+        assert_eq!(obj.groups[0].triangles[0],
+                   [vertex(0, Some(0), Some(0)),
+                    vertex(1, Some(1), Some(0)),
+                    vertex(2, Some(2), Some(0))]);
+    }
+
+    #[test]
+    fn normals_are_dropped_unless_every_vertex_has_one() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\nvn 0 0 1\n\
+                      f 1//1 2//1 3//1\nf 2 4 3\n";
+        let obj = parse(source).unwrap();
+        let triangles = &obj.groups[0].triangles;
+        assert_eq!(triangles[0][0], vertex(0, None, Some(0)));
+        assert_eq!(triangles[1][0], vertex(1, None, None));
+
+        let (vertices, indices) = mesh_arrays(triangles, &obj.positions, &obj.uvs, &obj.normals);
+        assert_eq!(indices.len(), 2);
+        assert!(vertices.normals.is_none());
+        assert!(vertices.uvs.is_none());
+
+        let obj = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//1\n").unwrap();
+        let (vertices, _) = mesh_arrays(&obj.groups[0].triangles,
+                                        &obj.positions,
+                                        &obj.uvs,
+                                        &obj.normals);
+        assert_eq!(vertices.normals, Some(vec![Vec3::new(0.0, 0.0, 1.0); 3]));
+    }
+
+    #[test]
+    fn unknown_materials_fall_back_to_the_default() {
+        let obj = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl missing\nf 1 2 3\n").unwrap();
+        assert_eq!(obj.groups[0].material, Some("missing".to_owned()));
+
+        let mut materials = HashMap::new();
+        materials.insert("red".to_owned(),
+                         Material { name: "red".to_owned(), ..Material::default() });
+        let mut used = Vec::new();
+        let meshes = obj.into_meshes(&materials, |material| {
+            used.push(material.name.clone());
+            Lambert::new(material.diffuse)
+        });
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(used, vec![String::new()]);
+    }
+
+    #[test]
+    fn parse_errors_have_line_numbers() {
+        match parse("v 0 0 0\n# comment\nv 1 0\n") {
+            Err(Error::Parse { file, line, .. }) => {
+                assert_eq!(file, PathBuf::from("test.obj"));
+                assert_eq!(line, 3);
+            }
+            _ => panic!("Expected a parse error"),
+        }
+
+        match parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n") {
+            Err(Error::Parse { line, .. }) => assert_eq!(line, 4),
+            _ => panic!("Expected a parse error"),
+        }
+
+        match parse_mtl("newmtl red\nKd 1 0 x\n".as_bytes(), Path::new("test.mtl")) {
+            Err(Error::Parse { file, line, .. }) => {
+                assert_eq!(file, PathBuf::from("test.mtl"));
+                assert_eq!(line, 2);
+            }
+            _ => panic!("Expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn material_libraries_are_relative_to_the_obj_file() {
+        let directory = env::temp_dir().join("libraytracer_obj_test");
+        fs::create_dir_all(directory.join("models")).unwrap();
+        let path = directory.join("models").join("triangle.obj");
+        File::create(&path)
+            .unwrap()
+            .write_all(b"mtllib triangle.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl red\nf 1 2 3\n")
+            .unwrap();
+        File::create(directory.join("models").join("triangle.mtl"))
+            .unwrap()
+            .write_all(b"newmtl red\nKd 1 0 0\n")
+            .unwrap();
+
+        let mut used = Vec::new();
+        let result = load(&path, |material| {
+            used.push(material.diffuse);
+            Lambert::new(material.diffuse)
+        });
+        let _ = fs::remove_dir_all(&directory);
+
+        assert_eq!(result.unwrap().len(), 1);
+        assert_eq!(used, vec![Rgb::new(1.0, 0.0, 0.0)]);
+    }
+}
@@ -0,0 +1,68 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// How far the rendering has come, reported after every finished tile.
+#[derive(Clone, Debug)]
+pub struct Progress {
+    /// The pass that's being rendered, starting at 0.
+    pub pass: usize,
+    pub pass_count: usize,
+    /// The tiles of the current pass that are done.
+    pub tiles_done: usize,
+    pub tile_count: usize,
+    /// The pixels of the current pass that are done.
+    pub pixels_done: usize,
+    pub pixel_count: usize,
+    /// The time since the first pass started.
+    pub elapsed: Duration,
+}
+
+fn to_seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9
+}
+
+impl Progress {
+    /// The fraction of all the passes that's done, in [0, 1].
+    pub fn fraction(&self) -> f32 {
+        if self.pass_count == 0 || self.tile_count == 0 {
+            return 1.0;
+        }
+        let pass = self.tiles_done as f32 / self.tile_count as f32;
+        (self.pass as f32 + pass) / self.pass_count as f32
+    }
+
+    /// Estimates the remaining time by assuming the rest takes as long as
+    /// what's already done. There's no estimate before anything is done.
+    pub fn remaining(&self) -> Option<Duration> {
+        let fraction = self.fraction() as f64;
+        if fraction <= 0.0 {
+            return None;
+        }
+        let remaining = to_seconds(self.elapsed) * (1.0 - fraction) / fraction;
+        Some(Duration::new(remaining as u64, (remaining.fract() * 1e9) as u32))
+    }
+}
+
+/// Stops the rendering from another thread. The threads finish the tiles
+/// they're working on, so every pixel either has the new sample or none of
+/// it. A token stays cancelled, so further passes stop right away until the
+/// ray tracer gets a new token.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken { cancelled: Arc::new(AtomicBool::new(false)) }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
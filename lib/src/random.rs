@@ -0,0 +1,34 @@
+use rand::{SeedableRng, XorShiftRng};
+
+/// The SplitMix64 finalizer, which scrambles similar inputs into unrelated
+/// outputs.
+pub fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+/// Combines all the values into a single well distributed hash.
+pub fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0, |hash, &value| mix(hash ^ value))
+}
+
+/// Maps the upper bits of a hash to [0, 1).
+pub fn to_float(hash: u64) -> f32 {
+    (hash >> 40) as f32 / (1u64 << 24) as f32
+}
+
+/// Creates the random number generator for a single sample of a pixel. It
+/// only depends on its arguments, so the random numbers don't depend on
+/// which thread renders the pixel.
+pub fn pixel_rng(seed: u64, pixel: (usize, usize), sample: u64) -> XorShiftRng {
+    let a = hash(&[seed, pixel.0 as u64, pixel.1 as u64, sample]);
+    let b = mix(a);
+    let mut state = [a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32];
+    if state == [0; 4] {
+        // An all zero state would only ever produce zeros
+        state[0] = 1;
+    }
+    XorShiftRng::from_seed(state)
+}
@@ -4,162 +4,851 @@ use entity::camera::Camera;
 use scoped_threadpool::Pool;
 use num_cpus;
 use ray::Ray;
+use aabb::Aabb;
+use bvh::Bvh;
+use collision::Collision;
+use light::{Light, LightSample};
+use environment::{Environment, Gradient, Sun};
+use tile::{self, TileOrder};
+use progress::{Progress, CancellationToken};
+use aov::{Aov, AovSample};
+use denoise::Denoiser;
+use exr;
 use nalgebra as na;
-use rand;
-use rand::{ThreadRng, Rng};
-use std::f32::consts::PI;
+use sampler::{Sampler, Independent};
+use std::collections::HashMap;
 use std::f32;
+use std::io;
+use std::ops::{Add, Mul, Div};
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+/// How the paths through the scene are followed.
+#[derive(Clone, Copy, Debug)]
+pub enum Mode {
+    /// Branches into `sample_count(depth)` BRDF samples at every bounce, so
+    /// the number of rays grows exponentially with the depth unless the
+    /// count falls off quickly. Paths end at the maximum depth.
+    Branching {
+        starting_samples: usize,
+        scale_factor: f32,
+    },
+    /// Continues every path with a single ray and keeps track of its
+    /// throughput. After `min_depth` bounces, paths are ended at random with
+    /// Russian roulette, which keeps the image unbiased. The maximum depth
+    /// only guards against paths that never end.
+    PathTracing { min_depth: usize },
+}
+
+/// Stops sampling the pixels whose estimated error is small enough, so that
+/// the following passes only spend time on the noisy ones.
+#[derive(Clone, Copy, Debug)]
+pub struct AdaptiveSampling {
+    /// The largest standard error of the luminance of a pixel, relative to
+    /// the luminance, that counts as converged.
+    pub threshold: f32,
+    /// Every pixel takes at least this many samples, as the variance of very
+    /// few samples says little about the actual noise.
+    pub min_samples: u32,
+}
 
 pub struct SamplingConfig {
     max_depth: usize,
-    starting_samples: usize,
-    scale_factor: f32,
+    mode: Mode,
 }
 
 impl SamplingConfig {
     pub fn new(max_depth: usize, starting_samples: usize, scale_factor: f32) -> Self {
         SamplingConfig {
             max_depth: max_depth,
-            starting_samples: starting_samples,
-            scale_factor: scale_factor,
+            mode: Mode::Branching {
+                starting_samples: starting_samples,
+                scale_factor: scale_factor,
+            },
+        }
+    }
+
+    pub fn path_tracing(min_depth: usize, max_depth: usize) -> Self {
+        SamplingConfig {
+            max_depth: max_depth,
+            mode: Mode::PathTracing { min_depth: min_depth },
         }
     }
 
     pub fn sample_count(&self, depth: usize) -> usize {
-        f32::round(self.starting_samples as f32 *
-                   f32::powi(self.scale_factor,
-                             -(depth as i32))) as usize
+        match self.mode {
+            Mode::Branching { starting_samples, scale_factor } => {
+                f32::round(starting_samples as f32 *
+                           f32::powi(scale_factor, -(depth as i32))) as usize
+            }
+            Mode::PathTracing { .. } => 1,
+        }
+    }
+}
+
+struct Scene<'a> {
+    entities: &'a [Box<Entity + Sync>],
+    bvh: &'a Bvh,
+    lights: &'a [Box<Light + Sync>],
+    environment: &'a (Environment + Sync),
+    /// The material ID of every entity.
+    material_ids: &'a [u32],
+}
+
+impl<'a> Scene<'a> {
+    fn collides_with(&self, ray: &Ray) -> Option<Collision<'a>> {
+        let entities = self.entities;
+        self.bvh.closest_hit(ray, |index| {
+            entities[index].collides_with(ray).map(|mut collision| {
+                collision.entity = index;
+                collision
+            })
+        })
+    }
+
+    fn is_occluded(&self, ray: &Ray, distance: f32) -> bool {
+        self.collides_with(ray).map_or(false, |collision| collision.distance < distance)
+    }
+
+    /// Finds the closest light whose surface the ray hits.
+    fn light_hit(&self, ray: &Ray) -> Option<(f32, Rgb, f32)> {
+        self.lights.iter().fold(None, |closest, light| {
+            match (closest, light.intersect(ray)) {
+                (Some(closest), Some(hit)) => {
+                    if hit.0 < closest.0 {
+                        Some(hit)
+                    } else {
+                        Some(closest)
+                    }
+                }
+                (closest, None) => closest,
+                (None, hit) => hit,
+            }
+        })
+    }
+}
+
+/// The radiance arriving along a path, split up by how many times the light
+/// bounced before it reached the camera.
+#[derive(Clone, Copy)]
+struct Radiance {
+    emission: Rgb,
+    direct: Rgb,
+    indirect: Rgb,
+}
+
+impl Radiance {
+    fn new() -> Self {
+        Radiance {
+            emission: Rgb::new(0.0, 0.0, 0.0),
+            direct: Rgb::new(0.0, 0.0, 0.0),
+            indirect: Rgb::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn add_light(&mut self, bounces: usize, value: Rgb) {
+        match bounces {
+            0 => self.emission = self.emission + value,
+            1 => self.direct = self.direct + value,
+            _ => self.indirect = self.indirect + value,
+        }
+    }
+
+    fn total(&self) -> Rgb {
+        self.emission + self.direct + self.indirect
+    }
+}
+
+impl Add for Radiance {
+    type Output = Radiance;
+
+    fn add(self, other: Radiance) -> Radiance {
+        Radiance {
+            emission: self.emission + other.emission,
+            direct: self.direct + other.direct,
+            indirect: self.indirect + other.indirect,
+        }
+    }
+}
+
+impl Mul<Rgb> for Radiance {
+    type Output = Radiance;
+
+    fn mul(self, factor: Rgb) -> Radiance {
+        Radiance {
+            emission: self.emission * factor,
+            direct: self.direct * factor,
+            indirect: self.indirect * factor,
+        }
+    }
+}
+
+impl Div<f32> for Radiance {
+    type Output = Radiance;
+
+    fn div(self, divisor: f32) -> Radiance {
+        Radiance {
+            emission: self.emission / divisor,
+            direct: self.direct / divisor,
+            indirect: self.indirect / divisor,
+        }
     }
 }
 
-pub struct RayTracer<BrdfType: Brdf + 'static> {
+/// Weighs a sample taken with a strategy of density `f_pdf` against another
+/// strategy of density `g_pdf` when combining them with multiple importance
+/// sampling. The densities need to be scaled by the number of samples taken
+/// with each of the strategies.
+fn power_heuristic(f_pdf: f32, g_pdf: f32) -> f32 {
+    let f2 = f_pdf * f_pdf;
+    let g2 = g_pdf * g_pdf;
+    if f2 + g2 > 0.0 {
+        f2 / (f2 + g2)
+    } else {
+        0.0
+    }
+}
+
+pub struct RayTracer {
     pub image: Image,
-    entities: Vec<Box<Entity<BrdfType = BrdfType> + Sync>>,
+    entities: Vec<Box<Entity + Sync>>,
+    /// The material ID of every entity, handed out in the order the BRDFs
+    /// got added.
+    material_ids: Vec<u32>,
+    /// Maps the identities of the BRDFs to their material IDs.
+    brdf_material_ids: HashMap<usize, u32>,
+    material_count: u32,
+    entity_bounds: Vec<Aabb>,
+    bvh: Bvh,
+    bvh_outdated: bool,
+    moved_entities: Vec<usize>,
+    lights: Vec<Box<Light + Sync>>,
+    environment: Box<Environment + Sync>,
     camera: Camera,
     thread_pool: Pool,
     sampling_config: SamplingConfig,
-    frames_rendered: u64,
-}
-
-fn random_dir(rng: &mut ThreadRng) -> Vec3 {
-    loop {
-        let x1 = rng.gen_range(-1.0, 1.0);
-        let x2 = rng.gen_range(-1.0, 1.0);
-        let x1sqr = x1 * x1;
-        let x2sqr = x2 * x2;
-        let sum = x1sqr + x2sqr;
-        if sum >= 1.0 {
-            continue;
-        }
-        let sqrt2 = 2.0 * f32::sqrt(1.0 - x1sqr - x2sqr);
-        let x = x1 * sqrt2;
-        let y = x2 * sqrt2;
-        let z = 1.0 - 2.0 * sum;
-        return Vec3::new(x, y, z);
-    }
+    sampler: Box<Sampler + Send>,
+    tile_size: usize,
+    tile_order: TileOrder,
+    adaptive_sampling: Option<AdaptiveSampling>,
+    aovs: Vec<(Aov, Image)>,
+    progress_callback: Option<Box<Fn(&Progress) + Send + Sync>>,
+    cancellation_token: CancellationToken,
+    seed: u64,
 }
 
-impl<BrdfType: Brdf + 'static> RayTracer<BrdfType> {
+impl RayTracer {
     pub fn new(camera: Camera, sampling_config: SamplingConfig) -> Self {
         RayTracer {
             image: Image::new(camera.dimensions),
             entities: Vec::new(),
+            material_ids: Vec::new(),
+            brdf_material_ids: HashMap::new(),
+            material_count: 0,
+            entity_bounds: Vec::new(),
+            bvh: Bvh::new(&[]),
+            bvh_outdated: false,
+            moved_entities: Vec::new(),
+            lights: Vec::new(),
+            environment: Box::new(Sun::new(Gradient::new(Rgb::new(0.75, 0.85, 1.0),
+                                                          Rgb::new(0.25, 0.55, 1.0)),
+                                           Vec3::new(0.75, 0.75, -0.75),
+                                           f32::acos(0.995),
+                                           Rgb::new(100.0, 100.0, 100.0))),
             camera: camera,
             thread_pool: Pool::new(num_cpus::get() as u32),
             sampling_config: sampling_config,
-            frames_rendered: 0,
+            sampler: Box::new(Independent::new()),
+            tile_size: 32,
+            tile_order: TileOrder::Scanline,
+            adaptive_sampling: None,
+            aovs: Vec::new(),
+            progress_callback: None,
+            cancellation_token: CancellationToken::new(),
+            seed: 0,
         }
     }
 
-    pub fn entity_mut(&mut self, index: usize) -> &mut Entity<BrdfType = BrdfType> {
+    /// The entity may get moved through the returned reference, so its
+    /// bounding box gets refit in the BVH before the next frame is rendered.
+    pub fn entity_mut(&mut self, index: usize) -> &mut Entity {
+        if !self.moved_entities.contains(&index) {
+            self.moved_entities.push(index);
+        }
         self.entities[index].as_mut()
     }
 
-    pub fn add_entity<T: Entity<BrdfType = BrdfType> + 'static + Sync>(&mut self, entity: T) {
-        self.entities.push(Box::new(entity))
+    pub fn add_entity<T: Entity + 'static + Sync>(&mut self, entity: T) {
+        let entity = Box::new(entity);
+
+        // Entities share a material ID if they share their BRDF. The identity
+        // is only stable once the entity is boxed.
+        let next_id = self.material_count;
+        let material_id = match entity.brdf().identity() {
+            Some(identity) => *self.brdf_material_ids.entry(identity).or_insert(next_id),
+            None => next_id,
+        };
+        if material_id == next_id {
+            self.material_count += 1;
+        }
+        self.material_ids.push(material_id);
+        self.entities.push(entity);
+        self.bvh_outdated = true;
+    }
+
+    pub fn add_light<T: Light + 'static + Sync>(&mut self, light: T) {
+        self.lights.push(Box::new(light));
+    }
+
+    /// Replaces the default environment, a bright sun over a blue gradient.
+    pub fn set_environment<T: Environment + 'static + Sync>(&mut self, environment: T) {
+        self.environment = Box::new(environment);
+    }
+
+    /// Replaces the default sampler, which uses independent random numbers.
+    pub fn set_sampler<T: Sampler + Send + 'static>(&mut self, sampler: T) {
+        self.sampler = Box::new(sampler);
+    }
+
+    /// The threads render the image in square tiles of this many pixels,
+    /// 32 by default. Every thread takes the next tile from a shared queue
+    /// when it's done with the last one.
+    pub fn set_tile_size(&mut self, tile_size: usize) {
+        assert!(tile_size > 0);
+        self.tile_size = tile_size;
+    }
+
+    pub fn set_tile_order(&mut self, tile_order: TileOrder) {
+        self.tile_order = tile_order;
+    }
+
+    /// Every pixel gets a sample in every pass unless adaptive sampling is
+    /// enabled.
+    pub fn set_adaptive_sampling(&mut self, adaptive_sampling: Option<AdaptiveSampling>) {
+        self.adaptive_sampling = adaptive_sampling;
+    }
+
+    /// Whether adaptive sampling considers every pixel converged, so further
+    /// passes wouldn't change the image anymore.
+    pub fn is_converged(&self) -> bool {
+        match self.adaptive_sampling {
+            Some(adaptive_sampling) => {
+                let (width, height) = self.image.dimensions;
+                let image = &self.image;
+                (0..height).all(|y| {
+                    (0..width).all(|x| Self::is_pixel_converged(image, (x, y), adaptive_sampling))
+                })
+            }
+            None => false,
+        }
+    }
+
+    fn is_pixel_converged(image: &Image,
+                          coord: (usize, usize),
+                          adaptive_sampling: AdaptiveSampling)
+                          -> bool {
+        image.sample_count(coord) >= adaptive_sampling.min_samples &&
+        image.relative_error(coord) <= adaptive_sampling.threshold
+    }
+
+    /// Renders the AOV alongside the image from the next pass on.
+    pub fn enable_aov(&mut self, aov: Aov) {
+        if self.aov(aov).is_none() {
+            self.aovs.push((aov, Image::new(self.camera.dimensions)));
+        }
+    }
+
+    pub fn aov(&self, aov: Aov) -> Option<&Image> {
+        self.aovs.iter().find(|&&(a, _)| a == aov).map(|&(_, ref image)| image)
+    }
+
+    /// Denoises the image, guided by the albedo and normal AOVs. Returns
+    /// `None` unless both of them are enabled.
+    pub fn denoised_image(&self, denoiser: &Denoiser) -> Option<Image> {
+        match (self.aov(Aov::Albedo), self.aov(Aov::Normal)) {
+            (Some(albedo), Some(normal)) => Some(denoiser.denoise(&self.image, albedo, normal)),
+            _ => None,
+        }
+    }
+
+    /// Saves an enabled AOV on its own as an OpenEXR image.
+    pub fn save_aov_exr<P: AsRef<Path>>(&self, aov: Aov, path: P) -> io::Result<()> {
+        let image = match self.aov(aov) {
+            Some(image) => image,
+            None => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          format!("The {} AOV isn't enabled", aov.name())))
+            }
+        };
+        exr::save(path, image.dimensions, &aov.exr_channels("", image.pixels()))
+    }
+
+    /// Saves the image together with all the enabled AOVs, which end up in
+    /// layers named after them, as a single OpenEXR image.
+    pub fn save_exr_layers<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut channels = exr::rgb_layer("", self.image.pixels());
+        for &(aov, ref image) in &self.aovs {
+            channels.extend(aov.exr_channels(aov.name(), image.pixels()));
+        }
+        exr::save(path, self.image.dimensions, &channels)
+    }
+
+    /// Gets called from the rendering threads whenever a tile is done.
+    pub fn set_progress_callback<F>(&mut self, callback: F)
+        where F: Fn(&Progress) + Send + Sync + 'static
+    {
+        self.progress_callback = Some(Box::new(callback));
+    }
+
+    /// Cancelling the token stops the pass that's being rendered, and any
+    /// further ones until another token is set.
+    pub fn set_cancellation_token(&mut self, cancellation_token: CancellationToken) {
+        self.cancellation_token = cancellation_token;
+    }
+
+    /// The random numbers of every sample only depend on the seed, the
+    /// pixel and how many samples it got before, so rendering the same
+    /// scene with the same seed always results in the same image.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    /// Defaults to the number of CPUs. It has no influence on the image.
+    pub fn set_thread_count(&mut self, thread_count: u32) {
+        self.thread_pool = Pool::new(thread_count);
     }
 
-    fn trace(entities: &[Box<Entity<BrdfType = BrdfType> + Sync>],
+    fn update_bvh(&mut self) {
+        if self.bvh_outdated {
+            self.entity_bounds = self.entities.iter().map(|e| e.bounds()).collect();
+            self.bvh = Bvh::new(&self.entity_bounds);
+            self.bvh_outdated = false;
+            self.moved_entities.clear();
+        } else {
+            for index in self.moved_entities.drain(..) {
+                self.entity_bounds[index] = self.entities[index].bounds();
+                self.bvh.refit(&self.entity_bounds, index);
+            }
+        }
+    }
+
+    /// Samples every light and the environment once from the collision and
+    /// weighs the samples against the `brdf_count` BRDF samples taken at the
+    /// same point.
+    fn sample_lights(scene: &Scene,
+                     collision: &Collision,
+                     normal: Vec3,
+                     view_direction: Vec3,
+                     brdf_count: usize,
+                     sampler: &mut Sampler)
+                     -> Rgb {
+        let origin = collision.position + normal * 0.001;
+
+        let contribution = |sample: LightSample| {
+            let n_dot_l = na::dot(&normal, &sample.direction);
+
+            if sample.pdf <= 0.0 || n_dot_l <= 0.0 {
+                return Rgb::new(0.0, 0.0, 0.0);
+            }
+
+            let shadow_ray = Ray::new(origin, sample.direction);
+            if scene.is_occluded(&shadow_ray, sample.distance) {
+                return Rgb::new(0.0, 0.0, 0.0);
+            }
+
+            let brdf = collision.brdf.solve(sample.direction, normal, view_direction);
+
+            let weight = if sample.is_delta || brdf_count == 0 {
+                1.0
+            } else {
+                let brdf_pdf = collision.brdf.pdf(sample.direction, normal, view_direction);
+                power_heuristic(sample.pdf, brdf_count as f32 * brdf_pdf)
+            };
+
+            brdf * sample.radiance * (n_dot_l * weight / sample.pdf)
+        };
+
+        let mut brightness = Rgb::new(0.0, 0.0, 0.0);
+
+        for light in scene.lights {
+            if let Some(sample) = light.sample(origin, sampler) {
+                brightness = brightness + contribution(sample);
+            }
+        }
+
+        if let Some(sample) = scene.environment.sample(sampler) {
+            brightness = brightness + contribution(sample);
+        }
+
+        brightness
+    }
+
+    /// `brdf_pdf` is the density the previous bounce sampled the ray with,
+    /// scaled by the number of samples it took. It's used to weigh hitting a
+    /// light by chance against sampling it explicitly.
+    fn trace(scene: &Scene,
              ray: &Ray,
+             brdf_pdf: Option<f32>,
              depth: usize,
-             config: &SamplingConfig)
-             -> Rgb {
-        for entity in entities {
-            if let Some(collision) = entity.collides_with(&ray) {
-                let view_direction = ray.direction * -1.0;
-                let mut brightness = Rgb::new(0.0, 0.0, 0.0);
-                let mut count = 0;
-
-                if depth < config.max_depth {
-                    count = config.sample_count(depth);
-                    let mut rng = rand::thread_rng();
-                    for _ in 0..count {
-                        let mut direction = random_dir(&mut rng);
-
-                        let mut n_dot_l = na::dot(&collision.normal, &direction);
-
-                        if n_dot_l < 0.0 {
-                            direction = direction * -1.0;
-                            n_dot_l = na::dot(&collision.normal, &direction);
-                        }
+             config: &SamplingConfig,
+             sampler: &mut Sampler)
+             -> Radiance {
+        let closest = scene.collides_with(ray);
+        let mut radiance = Radiance::new();
 
-                        let new_ray = Ray::new(collision.position + collision.normal * 0.001,
-                                               direction);
-                        let ray_brightness = Self::trace(entities, &new_ray, depth + 1, config);
-                        let mut brdf = collision.brdf
-                                                .solve(direction, collision.normal, view_direction);
-                        // brdf = brdf.saturate().fix_nan();
-                        brightness = brightness + brdf * ray_brightness * n_dot_l;
+        if let Some((distance, light_radiance, light_pdf)) = scene.light_hit(ray) {
+            if closest.as_ref().map_or(true, |collision| distance < collision.distance) {
+                let weight = match brdf_pdf {
+                    Some(brdf_pdf) => power_heuristic(brdf_pdf, light_pdf),
+                    None => 1.0,
+                };
+                radiance.add_light(depth, light_radiance * weight);
+                return radiance;
+            }
+        }
+
+        if let Some(collision) = closest {
+            let view_direction = ray.direction * -1.0;
+            let mut normal = collision.normal;
+            if na::dot(&normal, &view_direction) < 0.0 {
+                normal = normal * -1.0;
+            }
+
+            if depth < config.max_depth {
+                let count = config.sample_count(depth);
+
+                let direct = Self::sample_lights(scene,
+                                                 &collision,
+                                                 normal,
+                                                 view_direction,
+                                                 count,
+                                                 sampler);
+                radiance.add_light(depth + 1, direct);
+
+                let mut indirect = Radiance::new();
+
+                for _ in 0..count {
+                    let (direction, pdf, brdf) = collision.brdf.sample(view_direction,
+                                                                       normal,
+                                                                       sampler);
+
+                    let n_dot_l = na::dot(&normal, &direction);
+
+                    if pdf <= 0.0 || n_dot_l <= 0.0 {
+                        continue;
                     }
+
+                    let new_ray = Ray::new(collision.position + normal * 0.001, direction);
+                    let ray_brightness = Self::trace(scene,
+                                                     &new_ray,
+                                                     Some(count as f32 * pdf),
+                                                     depth + 1,
+                                                     config,
+                                                     sampler);
+                    // brdf = brdf.saturate().fix_nan();
+                    indirect = indirect + ray_brightness * (brdf * (n_dot_l / pdf));
                 }
 
                 if count > 0 {
-                    brightness = brightness / count as f32;
+                    radiance = radiance + indirect / count as f32;
                 }
+            }
 
-                brightness = brightness * PI + collision.brdf.solve_emissive();
+            radiance.add_light(depth, collision.brdf.solve_emissive());
 
-                return brightness;
+            return radiance;
 
-                // return Rgb::new(0.5 * collision.normal.x + 0.5, 0.5 * collision.normal.y + 0.5, 0.5 * collision.normal.z + 0.5);
-            }
+            // return Rgb::new(0.5 * collision.normal.x + 0.5, 0.5 * collision.normal.y + 0.5, 0.5 * collision.normal.z + 0.5);
         }
-        let sun_dir = na::normalize(&Vec3::new(0.75, 0.75, -0.75));
-        if na::dot(&ray.direction, &sun_dir) > 0.995 {
-            Rgb::new(100.0, 100.0, 100.0)
-        } else {
-            let t = 0.5 * ray.direction.y + 1.0;
-            Rgb::new(1.0, 1.0, 1.0) * (1.0 - t) + Rgb::new(0.5, 0.7, 1.0) * t
+
+        let weight = match brdf_pdf {
+            Some(brdf_pdf) => power_heuristic(brdf_pdf, scene.environment.pdf(ray.direction)),
+            None => 1.0,
+        };
+        radiance.add_light(depth, scene.environment.radiance(ray.direction) * weight);
+        radiance
+    }
+
+    /// Follows a single path from the camera ray. Every bounce samples the
+    /// lights and continues with one BRDF sample, weighted by the throughput
+    /// of the path so far.
+    fn trace_path(scene: &Scene,
+                  mut ray: Ray,
+                  min_depth: usize,
+                  config: &SamplingConfig,
+                  sampler: &mut Sampler)
+                  -> Radiance {
+        let mut radiance = Radiance::new();
+        let mut throughput = Rgb::new(1.0, 1.0, 1.0);
+        let mut brdf_pdf = None;
+
+        for depth in 0.. {
+            let closest = scene.collides_with(&ray);
+
+            if let Some((distance, light_radiance, light_pdf)) = scene.light_hit(&ray) {
+                if closest.as_ref().map_or(true, |collision| distance < collision.distance) {
+                    let weight = match brdf_pdf {
+                        Some(brdf_pdf) => power_heuristic(brdf_pdf, light_pdf),
+                        None => 1.0,
+                    };
+                    radiance.add_light(depth, throughput * light_radiance * weight);
+                    return radiance;
+                }
+            }
+
+            let collision = match closest {
+                Some(collision) => collision,
+                None => {
+                    let weight = match brdf_pdf {
+                        Some(brdf_pdf) => {
+                            power_heuristic(brdf_pdf, scene.environment.pdf(ray.direction))
+                        }
+                        None => 1.0,
+                    };
+                    let environment = scene.environment.radiance(ray.direction);
+                    radiance.add_light(depth, throughput * environment * weight);
+                    return radiance;
+                }
+            };
+
+            let view_direction = ray.direction * -1.0;
+            let mut normal = collision.normal;
+            if na::dot(&normal, &view_direction) < 0.0 {
+                normal = normal * -1.0;
+            }
+
+            radiance.add_light(depth, throughput * collision.brdf.solve_emissive());
+
+            if depth >= config.max_depth {
+                break;
+            }
+
+            let direct = Self::sample_lights(scene, &collision, normal, view_direction, 1, sampler);
+            radiance.add_light(depth + 1, throughput * direct);
+
+            let (direction, pdf, brdf) = collision.brdf.sample(view_direction, normal, sampler);
+            let n_dot_l = na::dot(&normal, &direction);
+            if pdf <= 0.0 || n_dot_l <= 0.0 {
+                break;
+            }
+            throughput = throughput * brdf * (n_dot_l / pdf);
+
+            if depth + 1 >= min_depth {
+                // Dim paths are likely to end, the ones that survive make up
+                // for the others by carrying more energy
+                let survival = f32::min(f32::max(throughput.red,
+                                                 f32::max(throughput.green, throughput.blue)),
+                                        0.95);
+                if !(sampler.next() < survival) {
+                    break;
+                }
+                throughput = throughput / survival;
+            }
+
+            ray = Ray::new(collision.position + normal * 0.001, direction);
+            brdf_pdf = Some(pdf);
         }
+
+        radiance
     }
 
     pub fn clear_image(&mut self) {
-        self.frames_rendered = 0;
+        self.image.clear();
+        for &mut (_, ref mut image) in &mut self.aovs {
+            image.clear();
+        }
+    }
+
+    /// Renders a single pass, which adds a sample to every pixel that
+    /// hasn't converged yet. Returns whether the pass got done before it was
+    /// cancelled.
+    pub fn render(&mut self) -> bool {
+        self.render_pass(0, 1, Instant::now())
+    }
+
+    /// Renders up to `passes` passes and returns how many of them got done.
+    /// It stops early when the rendering gets cancelled or, with adaptive
+    /// sampling, once every pixel has converged.
+    pub fn render_passes(&mut self, passes: usize) -> usize {
+        let start = Instant::now();
+        for pass in 0..passes {
+            if !self.render_pass(pass, passes, start) {
+                return pass;
+            }
+            if self.is_converged() {
+                return pass + 1;
+            }
+        }
+        passes
     }
 
-    pub fn render(&mut self) {
-        let image = &mut self.image;
+    fn render_pass(&mut self, pass: usize, pass_count: usize, start: Instant) -> bool {
+        if self.cancellation_token.is_cancelled() {
+            return false;
+        }
+
+        self.update_bvh();
+
+        let cancellation_token = &self.cancellation_token;
+
         let thread_count = self.thread_pool.thread_count() as usize;
         let camera = &self.camera;
-        let entities = &self.entities;
+        let scene = &Scene {
+            entities: &self.entities,
+            bvh: &self.bvh,
+            lights: &self.lights,
+            environment: self.environment.as_ref(),
+            material_ids: &self.material_ids,
+        };
+        let render_aovs = !self.aovs.is_empty();
         let sampling_config = &self.sampling_config;
+        let sampler = &self.sampler;
+        let seed = self.seed;
 
-        let old_frames_rendered = self.frames_rendered as f32;
-        let new_frames_rendered = (self.frames_rendered + 1) as f32;
-        let factor_new = 1.0 / new_frames_rendered;
-        let factor_old = old_frames_rendered / new_frames_rendered;
-        self.frames_rendered = self.frames_rendered + 1;
+        // The index of the next sample of every pixel, or `None` for the
+        // pixels that have converged
+        let (width, height) = self.image.dimensions;
+        let mut next_samples = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let converged = match self.adaptive_sampling {
+                    Some(adaptive_sampling) => {
+                        Self::is_pixel_converged(&self.image, (x, y), adaptive_sampling)
+                    }
+                    None => false,
+                };
+                next_samples.push(if converged {
+                    None
+                } else {
+                    Some(self.image.sample_count((x, y)) as u64)
+                });
+            }
+        }
+        let next_samples = &next_samples;
 
+        let tiles = &tile::tiles(self.image.dimensions, self.tile_size, self.tile_order);
+        let next_tile = &AtomicUsize::new(0);
+        let tiles_done = &AtomicUsize::new(0);
+        let pixels_done = &AtomicUsize::new(0);
+        let progress_callback = &self.progress_callback;
+        let images = &Mutex::new((&mut self.image, &mut self.aovs));
         self.thread_pool.scoped(|scope| {
-            for chunk in image.chunks_mut(thread_count) {
+            for _ in 0..thread_count {
+                let mut sampler = sampler.box_clone();
                 scope.execute(move || {
-                    for (coord, cell) in chunk {
-                        let ray = camera.get_ray_for_coordinate(coord);
-                        *cell = *cell * factor_old +
-                                Self::trace(entities, &ray, 0, sampling_config) * factor_new;
+                    while !cancellation_token.is_cancelled() {
+                        let tile = match tiles.get(next_tile.fetch_add(1, Ordering::Relaxed)) {
+                            Some(tile) => tile,
+                            None => break,
+                        };
+                        let mut samples = Vec::new();
+                        for (x, y) in tile.pixels() {
+                            if let Some(sample) = next_samples[y * width + x] {
+                                sampler.start_sample(seed, (x, y), sample);
+                                let (brightness, aovs) = Self::render_pixel(scene,
+                                                                            camera,
+                                                                            (x, y),
+                                                                            sampling_config,
+                                                                            sampler.as_mut(),
+                                                                            render_aovs);
+                                samples.push(((x, y), brightness, aovs));
+                            }
+                        }
+
+                        if !samples.is_empty() {
+                            // Only lock the images once the whole tile is done
+                            let mut images = images.lock().unwrap();
+                            let (ref mut image, ref mut aov_images) = *images;
+                            for (coord, brightness, aovs) in samples {
+                                image.add_sample(coord, brightness);
+                                for &mut (aov, ref mut aov_image) in aov_images.iter_mut() {
+                                    if aov.is_averaged() || aov_image.sample_count(coord) == 0 {
+                                        aov_image.add_sample(coord, aovs.value(aov));
+                                    }
+                                }
+                            }
+                        }
+
+                        let tiles_done = tiles_done.fetch_add(1, Ordering::SeqCst) + 1;
+                        let pixels_done = pixels_done.fetch_add(tile.width * tile.height,
+                                                                Ordering::SeqCst) +
+                                          tile.width * tile.height;
+                        if let Some(ref callback) = *progress_callback {
+                            callback(&Progress {
+                                pass: pass,
+                                pass_count: pass_count,
+                                tiles_done: tiles_done,
+                                tile_count: tiles.len(),
+                                pixels_done: pixels_done,
+                                pixel_count: width * height,
+                                elapsed: start.elapsed(),
+                            });
+                        }
                     }
                 });
             }
         });
+
+        tiles_done.load(Ordering::SeqCst) == tiles.len()
+    }
+
+    /// Takes a single sample of a pixel. The AOVs are only filled in when
+    /// `render_aovs` is set.
+    fn render_pixel(scene: &Scene,
+                    camera: &Camera,
+                    coord: (usize, usize),
+                    sampling_config: &SamplingConfig,
+                    sampler: &mut Sampler,
+                    render_aovs: bool)
+                    -> (Rgb, AovSample) {
+        let mut aovs = AovSample::new();
+        let ray = match camera.get_ray_for_coordinate(coord, sampler) {
+            Some(ray) => ray,
+            None => return (Rgb::new(0.0, 0.0, 0.0), aovs),
+        };
+
+        if render_aovs {
+            Self::primary_aovs(scene, &ray, &mut aovs);
+        }
+
+        let radiance = match sampling_config.mode {
+            Mode::Branching { .. } => Self::trace(scene, &ray, None, 0, sampling_config, sampler),
+            Mode::PathTracing { min_depth } => {
+                Self::trace_path(scene, ray, min_depth, sampling_config, sampler)
+            }
+        };
+        aovs.emission = radiance.emission;
+        aovs.direct = radiance.direct;
+        aovs.indirect = radiance.indirect;
+
+        (radiance.total(), aovs)
+    }
+
+    /// Fills in the AOVs that describe the surface the camera ray hits
+    /// first.
+    fn primary_aovs(scene: &Scene, ray: &Ray, aovs: &mut AovSample) {
+        let collision = match scene.collides_with(ray) {
+            Some(collision) => collision,
+            None => return,
+        };
+        if let Some((distance, _, _)) = scene.light_hit(ray) {
+            if distance < collision.distance {
+                return;
+            }
+        }
+
+        let mut normal = collision.normal;
+        if na::dot(&normal, &ray.direction) > 0.0 {
+            normal = normal * -1.0;
+        }
+
+        aovs.albedo = collision.brdf.albedo();
+        aovs.normal = normal;
+        aovs.depth = collision.distance;
+        aovs.position = collision.position;
+        aovs.object_id = collision.entity as u32 + 1;
+        aovs.material_id = scene.material_ids[collision.entity] + 1;
     }
 }
@@ -0,0 +1,170 @@
+use super::Sampler;
+use random;
+use std::sync::Arc;
+
+const SIZE: usize = 64;
+const SIGMA: f32 = 1.5;
+
+/// A binary pattern together with the energy that each of its set pixels
+/// spreads around itself. Dense clusters of set pixels have a high energy and
+/// large voids between them have a low one.
+struct Pattern {
+    pixels: Vec<bool>,
+    energy: Vec<f32>,
+    kernel: Vec<f32>,
+}
+
+impl Pattern {
+    fn new() -> Pattern {
+        let mut kernel = vec![0.0; SIZE * SIZE];
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                // The distance wraps around, so the mask tiles seamlessly
+                let dx = f32::min(x as f32, (SIZE - x) as f32);
+                let dy = f32::min(y as f32, (SIZE - y) as f32);
+                kernel[y * SIZE + x] = (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp();
+            }
+        }
+
+        Pattern {
+            pixels: vec![false; SIZE * SIZE],
+            energy: vec![0.0; SIZE * SIZE],
+            kernel: kernel,
+        }
+    }
+
+    fn set(&mut self, index: usize, value: bool) {
+        if self.pixels[index] == value {
+            return;
+        }
+        self.pixels[index] = value;
+
+        let sign = if value { 1.0 } else { -1.0 };
+        let (px, py) = (index % SIZE, index / SIZE);
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let dx = (x + SIZE - px) % SIZE;
+                let dy = (y + SIZE - py) % SIZE;
+                self.energy[y * SIZE + x] += sign * self.kernel[dy * SIZE + dx];
+            }
+        }
+    }
+
+    fn tightest_cluster(&self) -> usize {
+        self.find(true, |a, b| a > b)
+    }
+
+    fn largest_void(&self) -> usize {
+        self.find(false, |a, b| a < b)
+    }
+
+    fn find<F: Fn(f32, f32) -> bool>(&self, value: bool, better: F) -> usize {
+        let mut best = None;
+        for (i, &pixel) in self.pixels.iter().enumerate() {
+            if pixel != value {
+                continue;
+            }
+            best = match best {
+                Some(b) if !better(self.energy[i], self.energy[b]) => Some(b),
+                _ => Some(i),
+            };
+        }
+        best.expect("There is no pixel with the requested value")
+    }
+}
+
+/// Creates a tileable blue noise mask with the void-and-cluster method by
+/// Ulichney. Every pixel gets a unique rank in [0, 1) and pixels with similar
+/// ranks are spread evenly over the mask.
+fn void_and_cluster() -> Vec<f32> {
+    let count = SIZE * SIZE;
+    let mut pattern = Pattern::new();
+
+    // Start from a sparse random pattern and move its pixels out of clusters
+    // into voids, until the pixels are evenly distributed.
+    let initial = count / 10;
+    let mut i = 0;
+    while pattern.pixels.iter().filter(|&&p| p).count() < initial {
+        pattern.set(random::hash(&[i]) as usize % count, true);
+        i += 1;
+    }
+    loop {
+        let cluster = pattern.tightest_cluster();
+        pattern.set(cluster, false);
+        let void = pattern.largest_void();
+        if void == cluster {
+            pattern.set(cluster, true);
+            break;
+        }
+        pattern.set(void, true);
+    }
+
+    let mut ranks = vec![0; count];
+
+    // Rank the initial pixels by removing them from the tightest clusters
+    let mut removed = Pattern::new();
+    for (index, &pixel) in pattern.pixels.iter().enumerate() {
+        removed.set(index, pixel);
+    }
+    for rank in (0..initial).rev() {
+        let cluster = removed.tightest_cluster();
+        removed.set(cluster, false);
+        ranks[cluster] = rank;
+    }
+
+    // Rank all the remaining pixels by filling the largest voids
+    for rank in initial..count {
+        let void = pattern.largest_void();
+        pattern.set(void, true);
+        ranks[void] = rank;
+    }
+
+    ranks.iter().map(|&rank| (rank as f32 + 0.5) / count as f32).collect()
+}
+
+/// Distributes the error of neighboring pixels as blue noise, which looks
+/// less noisy than white noise at low sample counts. Every dimension of every
+/// sample index reads the same blue noise mask with a different random
+/// offset, so each pass is blue noise on its own.
+#[derive(Clone)]
+pub struct BlueNoise {
+    mask: Arc<Vec<f32>>,
+    seed: u64,
+    pixel: (usize, usize),
+    index: u64,
+    dimension: u64,
+}
+
+impl BlueNoise {
+    pub fn new() -> Self {
+        BlueNoise {
+            mask: Arc::new(void_and_cluster()),
+            seed: 0,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for BlueNoise {
+    fn start_sample(&mut self, seed: u64, pixel: (usize, usize), index: u64) {
+        self.seed = seed;
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn next(&mut self) -> f32 {
+        let offset = random::hash(&[self.seed, self.index, self.dimension]);
+        let x = (self.pixel.0 + offset as usize % SIZE) % SIZE;
+        let y = (self.pixel.1 + (offset >> 32) as usize % SIZE) % SIZE;
+        self.dimension += 1;
+
+        self.mask[y * SIZE + x]
+    }
+
+    fn box_clone(&self) -> Box<Sampler + Send> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,75 @@
+use super::Sampler;
+use random;
+use rand::{Rng, XorShiftRng};
+use std::f32;
+
+const PRIMES: [u64; 32] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67,
+                           71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131];
+
+fn radical_inverse(base: u64, mut index: u64) -> f32 {
+    let inverse_base = 1.0 / base as f64;
+    let mut factor = inverse_base;
+    let mut result = 0.0;
+    while index > 0 {
+        result += (index % base) as f64 * factor;
+        index /= base;
+        factor *= inverse_base;
+    }
+    result as f32
+}
+
+/// The Halton sequence, which uses the radical inverse in the n-th prime
+/// base for the n-th dimension. Every pixel gets its own random offset in
+/// every dimension (Cranley-Patterson rotation), so neighboring pixels don't
+/// use the exact same values. Dimensions beyond the 32nd are random.
+#[derive(Clone)]
+pub struct Halton {
+    seed: u64,
+    pixel: (usize, usize),
+    index: u64,
+    dimension: usize,
+    rng: XorShiftRng,
+}
+
+impl Halton {
+    pub fn new() -> Self {
+        Halton {
+            seed: 0,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+            rng: random::pixel_rng(0, (0, 0), 0),
+        }
+    }
+}
+
+impl Sampler for Halton {
+    fn start_sample(&mut self, seed: u64, pixel: (usize, usize), index: u64) {
+        self.seed = seed;
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+        self.rng = random::pixel_rng(seed, pixel, index);
+    }
+
+    fn next(&mut self) -> f32 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+
+        if dimension >= PRIMES.len() {
+            return self.rng.next_f32();
+        }
+
+        let offset = random::to_float(random::hash(&[self.seed,
+                                                     self.pixel.0 as u64,
+                                                     self.pixel.1 as u64,
+                                                     dimension as u64]));
+        let value = radical_inverse(PRIMES[dimension], self.index) + offset;
+        let value = value - value.floor();
+        f32::min(value, 1.0 - f32::EPSILON)
+    }
+
+    fn box_clone(&self) -> Box<Sampler + Send> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,29 @@
+use super::Sampler;
+use random;
+use rand::{Rng, XorShiftRng};
+
+/// Uniformly distributed random numbers without any structure.
+#[derive(Clone)]
+pub struct Independent {
+    rng: XorShiftRng,
+}
+
+impl Independent {
+    pub fn new() -> Self {
+        Independent { rng: random::pixel_rng(0, (0, 0), 0) }
+    }
+}
+
+impl Sampler for Independent {
+    fn start_sample(&mut self, seed: u64, pixel: (usize, usize), index: u64) {
+        self.rng = random::pixel_rng(seed, pixel, index);
+    }
+
+    fn next(&mut self) -> f32 {
+        self.rng.next_f32()
+    }
+
+    fn box_clone(&self) -> Box<Sampler + Send> {
+        Box::new(self.clone())
+    }
+}
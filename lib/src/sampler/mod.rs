@@ -0,0 +1,53 @@
+/// Provides the random numbers of the samples of a pixel. A sample consumes
+/// as many dimensions as it needs, one after another: the camera takes the
+/// first ones, followed by the lights and the BRDF at every bounce. Samplers
+/// other than `Independent` place the values of consecutive samples of a
+/// pixel more evenly than independent random numbers would, so the image
+/// converges faster.
+pub trait Sampler {
+    /// Starts the sample with the given index of a pixel. The values that
+    /// follow only depend on the arguments.
+    fn start_sample(&mut self, seed: u64, pixel: (usize, usize), index: u64);
+
+    /// The next dimension of the sample, in [0, 1).
+    fn next(&mut self) -> f32;
+
+    /// The next two dimensions of the sample, which are meant to be used
+    /// together, like the two coordinates of a point on a disc.
+    fn next_2d(&mut self) -> (f32, f32) {
+        (self.next(), self.next())
+    }
+
+    /// Every thread renders with a sampler of its own.
+    fn box_clone(&self) -> Box<Sampler + Send>;
+}
+
+impl<T: Sampler + ?Sized> Sampler for Box<T> {
+    fn start_sample(&mut self, seed: u64, pixel: (usize, usize), index: u64) {
+        (**self).start_sample(seed, pixel, index)
+    }
+
+    fn next(&mut self) -> f32 {
+        (**self).next()
+    }
+
+    fn next_2d(&mut self) -> (f32, f32) {
+        (**self).next_2d()
+    }
+
+    fn box_clone(&self) -> Box<Sampler + Send> {
+        (**self).box_clone()
+    }
+}
+
+pub mod independent;
+pub mod stratified;
+pub mod halton;
+pub mod sobol;
+pub mod blue_noise;
+
+pub use self::independent::Independent;
+pub use self::stratified::Stratified;
+pub use self::halton::Halton;
+pub use self::sobol::Sobol;
+pub use self::blue_noise::BlueNoise;
@@ -0,0 +1,108 @@
+use super::Sampler;
+use random;
+use std::f32;
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+fn reverse_bits(mut x: u32) -> u32 {
+    x = (x << 16) | (x >> 16);
+    x = ((x & 0x00ff00ff) << 8) | ((x & 0xff00ff00) >> 8);
+    x = ((x & 0x0f0f0f0f) << 4) | ((x & 0xf0f0f0f0) >> 4);
+    x = ((x & 0x33333333) << 2) | ((x & 0xcccccccc) >> 2);
+    ((x & 0x55555555) << 1) | ((x & 0xaaaaaaaa) >> 1)
+}
+
+/// Owen scrambling, which randomly flips the digits of a base 2 value
+/// depending on all the digits above them.
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    reverse_bits(laine_karras_permutation(reverse_bits(x), seed))
+}
+
+/// The first two dimensions of the Sobol sequence.
+fn sobol(index: u32) -> (u32, u32) {
+    let mut y = 0;
+    let mut direction = 1 << 31;
+    let mut i = index;
+    while i > 0 {
+        if i & 1 == 1 {
+            y ^= direction;
+        }
+        direction ^= direction >> 1;
+        i >>= 1;
+    }
+    (reverse_bits(index), y)
+}
+
+fn to_float(x: u32) -> f32 {
+    f32::min((x >> 8) as f32 / (1u32 << 24) as f32, 1.0 - f32::EPSILON)
+}
+
+/// Owen-scrambled Sobol points, following "Practical Hash-based Owen
+/// Scrambling" by Burley. Pairs of dimensions come from the first two
+/// dimensions of the Sobol sequence, shuffled and scrambled differently for
+/// every pixel and pair, which keeps them well stratified in 2D.
+#[derive(Clone)]
+pub struct Sobol {
+    seed: u64,
+    pixel: (usize, usize),
+    index: u64,
+    dimension: u64,
+}
+
+impl Sobol {
+    pub fn new() -> Self {
+        Sobol {
+            seed: 0,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn hash(&self, salt: u64) -> u32 {
+        random::hash(&[self.seed,
+                       self.pixel.0 as u64,
+                       self.pixel.1 as u64,
+                       self.dimension,
+                       salt]) as u32
+    }
+
+    fn shuffled_index(&self) -> u32 {
+        nested_uniform_scramble(self.index as u32, self.hash(0))
+    }
+}
+
+impl Sampler for Sobol {
+    fn start_sample(&mut self, seed: u64, pixel: (usize, usize), index: u64) {
+        self.seed = seed;
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn next(&mut self) -> f32 {
+        let (x, _) = sobol(self.shuffled_index());
+        let value = to_float(nested_uniform_scramble(x, self.hash(1)));
+        self.dimension += 1;
+        value
+    }
+
+    fn next_2d(&mut self) -> (f32, f32) {
+        let (x, y) = sobol(self.shuffled_index());
+        let value = (to_float(nested_uniform_scramble(x, self.hash(1))),
+                     to_float(nested_uniform_scramble(y, self.hash(2))));
+        self.dimension += 1;
+        value
+    }
+
+    fn box_clone(&self) -> Box<Sampler + Send> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,94 @@
+use super::Sampler;
+use random;
+use std::f32;
+
+/// Maps `i` to its position in a random permutation of `0..count` that's
+/// chosen by `seed`, without ever storing the permutation. See "Correlated
+/// Multi-Jittered Sampling" by Kensler.
+fn permute(mut i: u32, count: u32, seed: u32) -> u32 {
+    let mut w = count - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < count {
+            break;
+        }
+    }
+
+    (i.wrapping_add(seed)) % count
+}
+
+/// Jittered stratification of every dimension. Each run of `strata`
+/// consecutive samples of a pixel puts exactly one value into each of the
+/// `strata` equally sized intervals of every dimension, in an order that's
+/// shuffled independently for every dimension.
+#[derive(Clone)]
+pub struct Stratified {
+    strata: u32,
+    seed: u64,
+    pixel: (usize, usize),
+    index: u64,
+    dimension: u64,
+}
+
+impl Stratified {
+    pub fn new(strata: u32) -> Self {
+        assert!(strata > 0);
+        Stratified {
+            strata: strata,
+            seed: 0,
+            pixel: (0, 0),
+            index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for Stratified {
+    fn start_sample(&mut self, seed: u64, pixel: (usize, usize), index: u64) {
+        self.seed = seed;
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn next(&mut self) -> f32 {
+        let strata = self.strata as u64;
+        let (x, y) = (self.pixel.0 as u64, self.pixel.1 as u64);
+        let round = self.index / strata;
+
+        let permutation = random::hash(&[self.seed, x, y, round, self.dimension]);
+        let stratum = permute((self.index % strata) as u32, self.strata, permutation as u32);
+        let jitter = random::hash(&[self.seed, x, y, self.index, self.dimension, 1]);
+        let jitter = random::to_float(jitter);
+        self.dimension += 1;
+
+        f32::min((stratum as f32 + jitter) / self.strata as f32, 1.0 - f32::EPSILON)
+    }
+
+    fn box_clone(&self) -> Box<Sampler + Send> {
+        Box::new(self.clone())
+    }
+}
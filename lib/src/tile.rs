@@ -0,0 +1,134 @@
+/// The order in which the tiles of an image are handed out to the threads.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TileOrder {
+    /// Row by row from the top left.
+    Scanline,
+    /// Outwards from the center of the image, where the subject usually is.
+    Spiral,
+    /// Along a Hilbert curve, so that consecutive tiles are mostly next to
+    /// each other. The curve covers the smallest power of two square around
+    /// the grid, so it may jump where it leaves and reenters the image.
+    Hilbert,
+}
+
+/// A rectangular part of the image. The tiles at the right and bottom edges
+/// may be smaller than the rest.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Tile {
+    /// The coordinates of the pixels in the tile in row major order.
+    pub fn pixels(&self) -> Vec<(usize, usize)> {
+        let mut pixels = Vec::with_capacity(self.width * self.height);
+        for y in self.y..self.y + self.height {
+            for x in self.x..self.x + self.width {
+                pixels.push((x, y));
+            }
+        }
+        pixels
+    }
+}
+
+/// Splits the image into square tiles of `size` pixels.
+pub fn tiles((width, height): (usize, usize), size: usize, order: TileOrder) -> Vec<Tile> {
+    assert!(size > 0);
+    let columns = (width + size - 1) / size;
+    let rows = (height + size - 1) / size;
+
+    let positions = match order {
+        TileOrder::Scanline => scanline(columns, rows),
+        TileOrder::Spiral => spiral(columns, rows),
+        TileOrder::Hilbert => hilbert(columns, rows),
+    };
+
+    positions.into_iter()
+             .map(|(column, row)| {
+                 let (x, y) = (column * size, row * size);
+                 Tile {
+                     x: x,
+                     y: y,
+                     width: usize::min(size, width - x),
+                     height: usize::min(size, height - y),
+                 }
+             })
+             .collect()
+}
+
+fn scanline(columns: usize, rows: usize) -> Vec<(usize, usize)> {
+    let mut positions = Vec::with_capacity(columns * rows);
+    for row in 0..rows {
+        for column in 0..columns {
+            positions.push((column, row));
+        }
+    }
+    positions
+}
+
+fn spiral(columns: usize, rows: usize) -> Vec<(usize, usize)> {
+    let count = columns * rows;
+    let mut positions = Vec::with_capacity(count);
+    let (mut x, mut y) = ((columns as isize - 1) / 2, (rows as isize - 1) / 2);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut step_length = 1;
+    let mut direction = 0;
+
+    // Walks around the center in ever larger squares and skips the
+    // positions outside of the image
+    while positions.len() < count {
+        for _ in 0..2 {
+            for _ in 0..step_length {
+                if x >= 0 && y >= 0 && x < columns as isize && y < rows as isize {
+                    positions.push((x as usize, y as usize));
+                }
+                x += directions[direction].0;
+                y += directions[direction].1;
+            }
+            direction = (direction + 1) % 4;
+        }
+        step_length += 1;
+    }
+
+    positions
+}
+
+fn hilbert(columns: usize, rows: usize) -> Vec<(usize, usize)> {
+    let mut side = 1;
+    while side < columns || side < rows {
+        side *= 2;
+    }
+
+    (0..side * side)
+        .map(|index| hilbert_position(side, index))
+        .filter(|&(x, y)| x < columns && y < rows)
+        .collect()
+}
+
+/// Maps the distance along a Hilbert curve through a square with `side`
+/// cells (a power of two) to the position of the cell.
+fn hilbert_position(side: usize, mut index: usize) -> (usize, usize) {
+    let (mut x, mut y) = (0, 0);
+    let mut s = 1;
+    while s < side {
+        let rx = 1 & (index / 2);
+        let ry = 1 & (index ^ rx);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            let t = x;
+            x = y;
+            y = t;
+        }
+        x += s * rx;
+        y += s * ry;
+        index /= 4;
+        s *= 2;
+    }
+    (x, y)
+}
@@ -0,0 +1,150 @@
+//! Maps the unbounded radiance of a rendered image to displayable values.
+
+use prelude::*;
+use std::f32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operator {
+    /// Cuts off everything above 1.
+    Clamp,
+    /// Compresses the luminance with `L / (1 + L)`, which never reaches white.
+    Reinhard,
+    /// Reinhard with a luminance that gets mapped to white.
+    ExtendedReinhard { white: f32 },
+    /// The ACES filmic curve as fitted by Stephen Hill, including the
+    /// transforms into and out of the ACES color space.
+    Aces,
+    /// An approximation of the AgX display transform, which desaturates
+    /// bright colors smoothly instead of skewing their hue.
+    AgX,
+}
+
+impl Operator {
+    /// Every operator in the order a viewer could cycle through them in.
+    pub fn all() -> [Operator; 5] {
+        [Operator::Clamp,
+         Operator::Reinhard,
+         Operator::ExtendedReinhard { white: 4.0 },
+         Operator::Aces,
+         Operator::AgX]
+    }
+}
+
+/// Encodes the linear output of the operators for the display.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transfer {
+    Gamma(f32),
+    Srgb,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ToneMapper {
+    pub operator: Operator,
+    /// In stops, so every step of 1 doubles the brightness.
+    pub exposure: f32,
+    pub transfer: Transfer,
+}
+
+pub fn luminance(color: Rgb) -> f32 {
+    0.2126 * color.red + 0.7152 * color.green + 0.0722 * color.blue
+}
+
+fn scale_luminance<F: Fn(f32) -> f32>(color: Rgb, f: F) -> Rgb {
+    let l = luminance(color);
+    if l > 0.0 {
+        color * (f(l) / l)
+    } else {
+        Rgb::new(0.0, 0.0, 0.0)
+    }
+}
+
+fn transform(m: &[[f32; 3]; 3], c: Rgb) -> Rgb {
+    Rgb::new(m[0][0] * c.red + m[0][1] * c.green + m[0][2] * c.blue,
+             m[1][0] * c.red + m[1][1] * c.green + m[1][2] * c.blue,
+             m[2][0] * c.red + m[2][1] * c.green + m[2][2] * c.blue)
+}
+
+fn map_channels<F: Fn(f32) -> f32>(c: Rgb, f: F) -> Rgb {
+    Rgb::new(f(c.red), f(c.green), f(c.blue))
+}
+
+fn aces(color: Rgb) -> Rgb {
+    const INPUT: [[f32; 3]; 3] = [[0.59719, 0.35458, 0.04823],
+                                  [0.07600, 0.90834, 0.01566],
+                                  [0.02840, 0.13383, 0.83777]];
+    const OUTPUT: [[f32; 3]; 3] = [[1.60475, -0.53108, -0.07367],
+                                   [-0.10208, 1.10813, -0.00605],
+                                   [-0.00327, -0.07276, 1.07602]];
+
+    let color = transform(&INPUT, color);
+    // The reference rendering transform and the output device transform
+    let color = map_channels(color, |v| {
+        (v * (v + 0.0245786) - 0.000090537) / (v * (0.983729 * v + 0.4329510) + 0.238081)
+    });
+    transform(&OUTPUT, color)
+}
+
+/// See "Minimal AgX Implementation" by Benjamin Wrensch.
+fn agx(color: Rgb) -> Rgb {
+    const INSET: [[f32; 3]; 3] = [[0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+                                  [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+                                  [0.0423756549057051, 0.0784336, 0.879142973793104]];
+    const OUTSET: [[f32; 3]; 3] = [[1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+                                   [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+                                   [-0.0529716355144438, -0.0980434501171241, 1.15107367264116]];
+    const MIN_EV: f32 = -12.47393;
+    const MAX_EV: f32 = 4.026069;
+
+    let color = transform(&INSET, color);
+    let color = map_channels(color, |v| {
+        let v = f32::log2(f32::max(v, 1e-10)).clamp(MIN_EV, MAX_EV);
+        let x = (v - MIN_EV) / (MAX_EV - MIN_EV);
+        // A polynomial fit of the sigmoid contrast curve
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x -
+        0.00232
+    });
+    let color = transform(&OUTSET, color);
+    // The curve already includes a display encoding, which gets undone here
+    map_channels(color, |v| f32::powf(f32::max(v, 0.0), 2.2))
+}
+
+impl ToneMapper {
+    pub fn new(operator: Operator, exposure: f32, transfer: Transfer) -> Self {
+        ToneMapper {
+            operator: operator,
+            exposure: exposure,
+            transfer: transfer,
+        }
+    }
+
+    /// Maps radiance to display values between 0 and 1.
+    pub fn map(&self, radiance: Rgb) -> Rgb {
+        let color = radiance * f32::powf(2.0, self.exposure);
+
+        let color = match self.operator {
+            Operator::Clamp => color,
+            Operator::Reinhard => scale_luminance(color, |l| l / (1.0 + l)),
+            Operator::ExtendedReinhard { white } => {
+                scale_luminance(color, |l| l * (1.0 + l / (white * white)) / (1.0 + l))
+            }
+            Operator::Aces => aces(color),
+            Operator::AgX => agx(color),
+        };
+
+        map_channels(color, |v| {
+            let v = v.saturate();
+            match self.transfer {
+                Transfer::Gamma(gamma) => f32::powf(v, 1.0 / gamma),
+                Transfer::Srgb => {
+                    if v <= 0.0031308 {
+                        12.92 * v
+                    } else {
+                        1.055 * f32::powf(v, 1.0 / 2.4) - 0.055
+                    }
+                }
+            }
+        })
+    }
+}
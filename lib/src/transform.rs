@@ -0,0 +1,168 @@
+//! Affine transforms that place entities in the world. The entities
+//! intersect rays in their own object space, so the rays get transformed
+//! into it and the hits get transformed back out.
+
+use Vec3;
+use ray::Ray;
+use aabb::Aabb;
+use collision::Collision;
+use nalgebra as na;
+use nalgebra::Mat3;
+use std::f32;
+
+/// Maps object space to world space. The inverse of the linear part is kept
+/// around, as every ray needs it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    linear: Mat3<f32>,
+    inverse_linear: Mat3<f32>,
+    translation: Vec3,
+}
+
+impl Transform {
+    /// Returns `None` if the linear part can't be inverted.
+    pub fn new(linear: Mat3<f32>, translation: Vec3) -> Option<Self> {
+        na::inv(&linear).map(|inverse_linear| {
+            Transform {
+                linear: linear,
+                inverse_linear: inverse_linear,
+                translation: translation,
+            }
+        })
+    }
+
+    pub fn identity() -> Self {
+        Self::from_translation(Vec3::new(0.0, 0.0, 0.0))
+    }
+
+    pub fn from_translation(translation: Vec3) -> Self {
+        let identity = Mat3::new(1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0);
+        Transform {
+            linear: identity,
+            inverse_linear: identity,
+            translation: translation,
+        }
+    }
+
+    /// Scales along the axes. Panics if any of the factors is 0.
+    pub fn from_scale(factors: Vec3) -> Self {
+        assert!(factors.x != 0.0 && factors.y != 0.0 && factors.z != 0.0,
+                "Can't scale by 0");
+        Transform {
+            linear: Mat3::new(factors.x, 0.0, 0.0, 0.0, factors.y, 0.0, 0.0, 0.0, factors.z),
+            inverse_linear: Mat3::new(1.0 / factors.x,
+                                      0.0,
+                                      0.0,
+                                      0.0,
+                                      1.0 / factors.y,
+                                      0.0,
+                                      0.0,
+                                      0.0,
+                                      1.0 / factors.z),
+            translation: Vec3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Rotates counterclockwise around the axis by the angle in radians,
+    /// when looking at it from its tip.
+    pub fn from_rotation(axis: Vec3, angle: f32) -> Self {
+        let Vec3 { x, y, z } = na::normalize(&axis);
+        let (sin, cos) = (f32::sin(angle), f32::cos(angle));
+        let k = 1.0 - cos;
+        let rotation = Mat3::new(cos + x * x * k,
+                                 x * y * k - z * sin,
+                                 x * z * k + y * sin,
+                                 y * x * k + z * sin,
+                                 cos + y * y * k,
+                                 y * z * k - x * sin,
+                                 z * x * k - y * sin,
+                                 z * y * k + x * sin,
+                                 cos + z * z * k);
+        Transform {
+            linear: rotation,
+            inverse_linear: na::transpose(&rotation),
+            translation: Vec3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Applies this transform first and `next` afterwards.
+    pub fn then(&self, next: &Transform) -> Transform {
+        Transform {
+            linear: next.linear * self.linear,
+            inverse_linear: self.inverse_linear * next.inverse_linear,
+            translation: next.linear * self.translation + next.translation,
+        }
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform {
+            linear: self.inverse_linear,
+            inverse_linear: self.linear,
+            translation: (self.inverse_linear * self.translation) * -1.0,
+        }
+    }
+
+    pub fn linear(&self) -> Mat3<f32> {
+        self.linear
+    }
+
+    /// Where the origin of object space ends up.
+    pub fn translation(&self) -> Vec3 {
+        self.translation
+    }
+
+    pub fn set_translation(&mut self, translation: Vec3) {
+        self.translation = translation;
+    }
+
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        self.linear * point + self.translation
+    }
+
+    pub fn transform_vector(&self, vector: Vec3) -> Vec3 {
+        self.linear * vector
+    }
+
+    /// Normals need the inverse transpose to stay perpendicular to the
+    /// surface under non-uniform scaling. The result is normalized.
+    pub fn transform_normal(&self, normal: Vec3) -> Vec3 {
+        na::normalize(&(na::transpose(&self.inverse_linear) * normal))
+    }
+
+    /// Takes a world space ray into object space. The direction doesn't get
+    /// normalized, so that distances along it stay the same in both spaces.
+    pub fn ray_to_object(&self, ray: &Ray) -> Ray {
+        Ray::new(self.inverse_linear * (ray.origin - self.translation),
+                 self.inverse_linear * ray.direction)
+    }
+
+    /// Takes a collision with a ray from `ray_to_object` back into world
+    /// space.
+    pub fn collision_to_world<'a>(&self,
+                                  ray: &Ray,
+                                  mut collision: Collision<'a>)
+                                  -> Collision<'a> {
+        collision.position = ray.direction * collision.distance + ray.origin;
+        collision.normal = self.transform_normal(collision.normal);
+        collision.tangent = collision.tangent.map(|tangent| {
+            na::normalize(&self.transform_vector(tangent))
+        });
+        collision
+    }
+
+    /// The world space box around the transformed corners of an object space
+    /// box.
+    pub fn transform_aabb(&self, aabb: &Aabb) -> Aabb {
+        if aabb.is_empty() {
+            return *aabb;
+        }
+        let mut bounds = Aabb::empty();
+        for corner in 0..8 {
+            let point = Vec3::new(if corner & 1 == 0 { aabb.min.x } else { aabb.max.x },
+                                  if corner & 2 == 0 { aabb.min.y } else { aabb.max.y },
+                                  if corner & 4 == 0 { aabb.min.z } else { aabb.max.z });
+            bounds = bounds.grow(self.transform_point(point));
+        }
+        bounds
+    }
+}
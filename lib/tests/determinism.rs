@@ -0,0 +1,113 @@
+extern crate libraytracer;
+
+use libraytracer::prelude::*;
+use libraytracer::{RayTracer, Camera, SamplingConfig, AdaptiveSampling, Sampler};
+use libraytracer::entity::Sphere;
+use libraytracer::brdf::{Lambert, UnlimitedChromatic};
+use libraytracer::light::PointLight;
+use libraytracer::sampler::{Independent, Stratified, Halton, Sobol, BlueNoise};
+use libraytracer::tile::TileOrder;
+use std::f32;
+
+fn render_with<F>(seed: u64, thread_count: u32, configure: F) -> Vec<[u32; 3]>
+    where F: FnOnce(&mut RayTracer)
+{
+    let camera = Camera::new((32, 24), Vec3::new(0.0, 0.0, 0.0), f32::consts::FRAC_PI_2);
+    let mut raytracer = RayTracer::new(camera, SamplingConfig::new(3, 2, 2.0));
+    configure(&mut raytracer);
+    raytracer.set_seed(seed);
+    raytracer.set_thread_count(thread_count);
+
+    raytracer.add_entity(Sphere::new(Vec3::new(-1.5, 0.0, 5.0),
+                                     1.0,
+                                     UnlimitedChromatic::new(Rgb::new(0.8, 0.4, 0.0),
+                                                             Rgb::new(0.2, 0.2, 0.2),
+                                                             0.3)));
+    raytracer.add_entity(Sphere::new(Vec3::new(1.5, 0.0, 5.0),
+                                     1.0,
+                                     Lambert::new(Rgb::new(0.1, 0.3, 0.8))));
+    raytracer.add_entity(Sphere::new(Vec3::new(0.0, -1001.0, 5.0),
+                                     1000.0,
+                                     Lambert::new(Rgb::new(0.7, 0.7, 0.7))));
+    raytracer.add_light(PointLight::new(Vec3::new(0.0, 4.0, 3.0), Rgb::new(20.0, 20.0, 20.0)));
+
+    for _ in 0..3 {
+        raytracer.render();
+    }
+
+    // Compare the exact bits, so that not even rounding differences go unnoticed
+    raytracer.image
+             .pixels()
+             .iter()
+             .map(|p| [p.red.to_bits(), p.green.to_bits(), p.blue.to_bits()])
+             .collect()
+}
+
+fn render(seed: u64, thread_count: u32) -> Vec<[u32; 3]> {
+    render_with(seed, thread_count, |raytracer| raytracer.set_sampler(Independent::new()))
+}
+
+#[test]
+fn identical_across_runs() {
+    assert!(render(42, 2) == render(42, 2));
+}
+
+#[test]
+fn identical_across_thread_counts() {
+    let reference = render(42, 1);
+    for &thread_count in &[2, 3, 8] {
+        assert!(render(42, thread_count) == reference,
+                "The image differs with {} threads",
+                thread_count);
+    }
+}
+
+#[test]
+fn seed_changes_the_image() {
+    assert!(render(1, 2) != render(2, 2));
+}
+
+#[test]
+fn samplers_identical_across_thread_counts() {
+    let samplers: Vec<Box<Sampler + Send>> = vec![Box::new(Stratified::new(4)),
+                                                  Box::new(Halton::new()),
+                                                  Box::new(Sobol::new()),
+                                                  Box::new(BlueNoise::new())];
+    for sampler in samplers {
+        let reference = render_with(42, 1, |raytracer| raytracer.set_sampler(sampler.box_clone()));
+        assert!(render_with(42, 3, |raytracer| raytracer.set_sampler(sampler)) == reference);
+    }
+}
+
+#[test]
+fn identical_across_tiles() {
+    let reference = render(42, 1);
+    for &tile_size in &[1, 5, 16, 64] {
+        for &tile_order in &[TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            let image = render_with(42, 4, |raytracer| {
+                raytracer.set_tile_size(tile_size);
+                raytracer.set_tile_order(tile_order);
+            });
+            assert!(image == reference,
+                    "The image differs with {:?} tiles of {} pixels",
+                    tile_order,
+                    tile_size);
+        }
+    }
+}
+
+#[test]
+fn adaptive_sampling_identical_across_thread_counts() {
+    let adaptive_sampling = AdaptiveSampling {
+        threshold: 0.1,
+        min_samples: 2,
+    };
+    let reference = render_with(42, 1, |raytracer| {
+        raytracer.set_adaptive_sampling(Some(adaptive_sampling))
+    });
+    let image = render_with(42, 3, |raytracer| {
+        raytracer.set_adaptive_sampling(Some(adaptive_sampling))
+    });
+    assert!(image == reference);
+    assert!(reference != render(42, 1));
+}